@@ -0,0 +1,122 @@
+use std::hash::Hash;
+
+use crate::cache::{lru::Lru, policy::Policy};
+
+/// Adaptive Replacement Cache (Megiddo and Modha): keys seen once and keys seen again
+/// are kept apart, and ghost lists of their evicted keys shift the balance between them.
+/// The number of cached keys stands in for the fixed size of the original.
+pub struct AdaptiveReplacement<K> {
+    recent: Lru<K>,          // T1, used once since cached
+    frequent: Lru<K>,        // T2, used again
+    recent_ghosts: Lru<K>,   // B1
+    frequent_ghosts: Lru<K>, // B2
+    target: usize,           // p, how many of the cached keys `recent` should hold
+}
+
+impl<K: Hash + Eq + Clone> AdaptiveReplacement<K> {
+    pub fn new() -> AdaptiveReplacement<K> {
+        AdaptiveReplacement {
+            recent: Lru::new(),
+            frequent: Lru::new(),
+            recent_ghosts: Lru::new(),
+            frequent_ghosts: Lru::new(),
+            target: 0,
+        }
+    }
+
+    fn cached(&self) -> usize {
+        (self.recent.len() + self.frequent.len()).max(1)
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send> Policy<K> for AdaptiveReplacement<K> {
+    fn touch(&mut self, key: &K) {
+        if self.recent.contains(key) {
+            self.recent.remove(key);
+            self.frequent.touch(key);
+        } else if self.frequent.contains(key) {
+            self.frequent.touch(key);
+        }
+    }
+
+    fn insert(&mut self, key: &K) {
+        if self.recent.contains(key) || self.frequent.contains(key) {
+            return self.touch(key);
+        }
+        // a ghost hit means its list was evicted from too eagerly
+        if self.recent_ghosts.contains(key) {
+            let step = (self.frequent_ghosts.len() / self.recent_ghosts.len()).max(1);
+            self.target = (self.target + step).min(self.cached());
+            self.recent_ghosts.remove(key);
+            self.frequent.touch(key);
+        } else if self.frequent_ghosts.contains(key) {
+            let step = (self.recent_ghosts.len() / self.frequent_ghosts.len()).max(1);
+            self.target = self.target.saturating_sub(step);
+            self.frequent_ghosts.remove(key);
+            self.frequent.touch(key);
+        } else {
+            self.recent.touch(key);
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.recent.remove(key);
+        self.frequent.remove(key);
+        self.recent_ghosts.remove(key);
+        self.frequent_ghosts.remove(key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        let key = if !self.recent.is_empty()
+            && (self.recent.len() > self.target || self.frequent.is_empty())
+        {
+            let key = self.recent.pop()?;
+            self.recent_ghosts.touch(&key);
+            key
+        } else {
+            let key = self.frequent.pop()?;
+            self.frequent_ghosts.touch(&key);
+            key
+        };
+        // ghosts are only remembered for about as many keys as are cached
+        let cached = self.cached();
+        while self.recent_ghosts.len() > cached {
+            self.recent_ghosts.pop();
+        }
+        while self.frequent_ghosts.len() > cached {
+            self.frequent_ghosts.pop();
+        }
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scans_only_evict_recent_keys() {
+        let mut arc = AdaptiveReplacement::new();
+        for key in 0..4 {
+            arc.insert(&key);
+            arc.touch(&key);
+        }
+        // a long scan of keys used once, the cache holds five
+        for key in 100..120 {
+            arc.insert(&key);
+            if arc.recent.len() + arc.frequent.len() > 5 {
+                let evicted = arc.evict().unwrap();
+                assert!(evicted >= 100, "evicted frequent key {}", evicted);
+            }
+        }
+        assert_eq!(arc.frequent.len(), 4);
+
+        // a key evicted from the recent list comes back as frequent, and makes room for recent keys
+        assert!(arc.recent_ghosts.contains(&118));
+        arc.insert(&118);
+        assert!(arc.frequent.contains(&118));
+        assert!(arc.target > 0);
+        arc.remove(&0);
+        assert!(!arc.frequent.contains(&0));
+    }
+}
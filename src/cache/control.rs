@@ -0,0 +1,216 @@
+use std::time::{Duration, SystemTime};
+
+use crate::http_parser::{headers::Headers, request::Request, response::ResponseHead};
+
+/// Statuses a cache understands well enough to store (RFC 9110 section 15.1)
+const CACHEABLE_STATUSES: [u16; 13] = [
+    200, 203, 204, 300, 301, 302, 307, 308, 404, 405, 410, 414, 501,
+];
+
+/// Directives of the `Cache-Control` fields of a message (RFC 9111 section 5.2)
+#[derive(Debug, Default, PartialEq)]
+pub struct CacheControl {
+    pub max_age: Option<u64>,
+    pub s_maxage: Option<u64>,
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub private: bool,
+    pub public: bool,
+    pub must_revalidate: bool, // `proxy-revalidate` too
+}
+
+/// How long a stored response may be served without asking the upstream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Freshness {
+    pub lifetime: Duration,
+    pub initial_age: Duration, // `Age` of the response when it was received
+    pub must_revalidate: bool, // stale copies are never served, even when the upstream is down
+}
+
+impl CacheControl {
+    pub fn new(headers: &Headers) -> CacheControl {
+        let mut control = CacheControl::default();
+        let directives = headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("cache-control"))
+            .flat_map(|(_, value)| value.split(','));
+        for directive in directives {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name, Some(argument.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            let seconds = argument.and_then(|argument| argument.parse().ok());
+            // qualified forms like `private="Set-Cookie"` are treated as the plain directive
+            match name.trim().to_ascii_lowercase().as_str() {
+                "max-age" => control.max_age = seconds.or(Some(0)),
+                "s-maxage" => control.s_maxage = seconds.or(Some(0)),
+                "no-store" => control.no_store = true,
+                "no-cache" => control.no_cache = true,
+                "private" => control.private = true,
+                "public" => control.public = true,
+                "must-revalidate" | "proxy-revalidate" => control.must_revalidate = true,
+                _ => {}
+            }
+        }
+        control
+    }
+}
+
+/// Whether the client asks for a response checked with the upstream, even if a fresh one is cached
+pub fn requires_revalidation(request: &Request) -> bool {
+    let control = CacheControl::new(&request.headers);
+    match request.headers.get("cache-control") {
+        Some(_) => control.no_cache || control.max_age == Some(0),
+        None => request.headers.has_token("pragma", "no-cache"),
+    }
+}
+
+/// Freshness of a response to `request` a shared cache may store (RFC 9111 section 3),
+/// `None` when it must not be stored. Responses without explicit freshness aren't stored.
+pub fn storable(request: &Request, response: &ResponseHead) -> Option<Freshness> {
+    let control = CacheControl::new(&response.headers);
+    if !request.method.eq_ignore_ascii_case("get")
+        || CacheControl::new(&request.headers).no_store
+        || !CACHEABLE_STATUSES.contains(&response.status)
+        || control.no_store
+        || control.private
+        || response.headers.has_token("vary", "*")
+        || response.headers.get("set-cookie").is_some()
+    {
+        return None;
+    }
+    if request.headers.get("authorization").is_some()
+        && !(control.public || control.s_maxage.is_some() || control.must_revalidate)
+    {
+        return None;
+    }
+
+    let lifetime = match control.s_maxage.or(control.max_age) {
+        Some(seconds) => Duration::from_secs(seconds),
+        None if control.no_cache => Duration::ZERO,
+        None => {
+            let expires = response.headers.get("expires")?;
+            // invalid dates such as `0` mean already expired
+            let expires =
+                httpdate::parse_http_date(expires.trim()).unwrap_or(SystemTime::UNIX_EPOCH);
+            let date = response
+                .headers
+                .get("date")
+                .and_then(|date| httpdate::parse_http_date(date.trim()).ok())
+                .unwrap_or_else(SystemTime::now);
+            expires.duration_since(date).unwrap_or(Duration::ZERO)
+        }
+    };
+    let has_validators =
+        response.headers.get("etag").is_some() || response.headers.get("last-modified").is_some();
+    if lifetime.is_zero() && !has_validators {
+        return None;
+    }
+
+    Some(Freshness {
+        lifetime,
+        initial_age: Duration::from_secs(
+            response
+                .headers
+                .get("age")
+                .and_then(|age| age.trim().parse().ok())
+                .unwrap_or(0),
+        ),
+        must_revalidate: control.must_revalidate || control.no_cache,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_parser::{request::parse_request, response::parse_response};
+
+    fn request(headers: &str) -> Request {
+        let head = format!("GET / HTTP/1.1\r\nHost: a\r\n{}\r\n", headers);
+        parse_request(head.as_bytes()).unwrap()
+    }
+
+    fn freshness(request_headers: &str, status: u16, headers: &str) -> Option<Freshness> {
+        let head = format!("HTTP/1.1 {} X\r\n{}\r\n", status, headers);
+        storable(
+            &request(request_headers),
+            &parse_response(head.as_bytes()).unwrap(),
+        )
+    }
+
+    fn lifetime(headers: &str) -> Option<u64> {
+        freshness("", 200, headers).map(|freshness| freshness.lifetime.as_secs())
+    }
+
+    #[test]
+    fn test_storable() {
+        assert_eq!(lifetime("Cache-Control: max-age=60\r\n"), Some(60));
+        assert_eq!(
+            lifetime("Cache-Control: public, max-age=60, s-maxage=\"30\"\r\n"),
+            Some(30)
+        );
+        assert_eq!(
+            lifetime(
+                "Date: Sun, 06 Nov 1994 08:49:37 GMT\r\nExpires: Sun, 06 Nov 1994 08:50:37 GMT\r\n"
+            ),
+            Some(60)
+        );
+        assert_eq!(lifetime("Expires: 0\r\nETag: \"a\"\r\n"), Some(0));
+        assert_eq!(
+            lifetime("Cache-Control: no-cache\r\nETag: \"a\"\r\n"),
+            Some(0)
+        );
+        assert_eq!(lifetime("Cache-Control: no-cache\r\n"), None);
+        assert_eq!(lifetime("ETag: \"a\"\r\n"), None);
+        assert_eq!(lifetime("Cache-Control: max-age=60, no-store\r\n"), None);
+        assert_eq!(lifetime("Cache-Control: private, max-age=60\r\n"), None);
+        assert_eq!(lifetime("Cache-Control: max-age=60\r\nVary: *\r\n"), None);
+        assert_eq!(
+            lifetime("Cache-Control: max-age=60\r\nSet-Cookie: a=b\r\n"),
+            None
+        );
+        assert!(freshness("", 206, "Cache-Control: max-age=60\r\n").is_none());
+        assert!(
+            freshness(
+                "Cache-Control: no-store\r\n",
+                200,
+                "Cache-Control: max-age=60\r\n"
+            )
+            .is_none()
+        );
+
+        assert!(freshness("Authorization: x\r\n", 200, "Cache-Control: max-age=60\r\n").is_none());
+        assert!(
+            freshness(
+                "Authorization: x\r\n",
+                200,
+                "Cache-Control: s-maxage=60\r\n"
+            )
+            .is_some()
+        );
+
+        let freshness = freshness(
+            "",
+            404,
+            "Cache-Control: max-age=60, must-revalidate\r\nAge: 10\r\n",
+        )
+        .unwrap();
+        assert_eq!(freshness.initial_age, Duration::from_secs(10));
+        assert!(freshness.must_revalidate);
+    }
+
+    #[test]
+    fn test_requires_revalidation() {
+        assert!(!requires_revalidation(&request("")));
+        assert!(requires_revalidation(&request(
+            "Cache-Control: no-cache\r\n"
+        )));
+        assert!(requires_revalidation(&request(
+            "Cache-Control: max-age=0\r\n"
+        )));
+        assert!(requires_revalidation(&request("Pragma: no-cache\r\n")));
+        assert!(!requires_revalidation(&request(
+            "Cache-Control: max-age=60\r\nPragma: no-cache\r\n"
+        )));
+    }
+}
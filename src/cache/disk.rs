@@ -0,0 +1,386 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Error, Read},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
+
+use crate::cache::lru::Lru;
+
+/// Written in front of every file, entries from another format are ignored
+const MAGIC: &[u8] = b"rs-ngnix cache 1\n";
+/// Files being written, moved into place once complete
+const TEMP_DIRECTORY: &str = "tmp";
+
+/// Tells apart the temp directories of caches on the same path, e.g. before and after a reload
+static INSTANCES: AtomicU64 = AtomicU64::new(0);
+
+/// Entries kept on disk below the memory cache, surviving reloads and restarts.
+/// Files live at `<directory>/<c>/<ba>/<hash>` like nginx `levels=1:2`, named after a hash of their key.
+pub struct DiskCache {
+    directory: PathBuf,
+    temp: PathBuf, // this instance's own, others may still be writing to theirs
+    max_size: u64,
+    index: Arc<Mutex<DiskIndex>>,
+    writes: AtomicU64, // tells temporary files apart
+}
+
+/// Sizes of the files on disk, rebuilt from the directory at startup
+struct DiskIndex {
+    sizes: HashMap<u64, u64>,
+    recency: Lru<u64>,
+    size: u64,
+    scanned: bool, // until then files may exist that aren't indexed yet
+}
+
+impl DiskCache {
+    /// Indexes what a previous run left in `directory` in the background,
+    /// oldest files first in line for eviction
+    pub fn new(directory: &Path, max_size: u64) -> Result<DiskCache, Error> {
+        let temp = directory.join(TEMP_DIRECTORY).join(format!(
+            "{}.{}",
+            std::process::id(),
+            INSTANCES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&temp)?;
+
+        let cache = DiskCache {
+            directory: directory.to_path_buf(),
+            temp,
+            max_size,
+            index: Arc::new(Mutex::new(DiskIndex {
+                sizes: HashMap::new(),
+                recency: Lru::new(),
+                size: 0,
+                scanned: false,
+            })),
+            writes: AtomicU64::new(0),
+        };
+        let directory = cache.directory.clone();
+        let index = cache.index.clone();
+        tokio::task::spawn_blocking(move || {
+            let files = scan(&directory);
+            let victims = {
+                let mut index = index.lock().unwrap_or_else(PoisonError::into_inner);
+                let victims = index.merge(files, max_size);
+                println!(
+                    "Disk cache {:?}: {} entries, {} KB",
+                    directory,
+                    index.sizes.len(),
+                    index.size / 1024
+                );
+                victims
+            };
+            for victim in victims {
+                let _ = fs::remove_file(path(&directory, victim));
+            }
+        });
+        Ok(cache)
+    }
+
+    /// Largest entry worth writing, a single one never takes more than an eighth of the tier
+    pub fn max_entry_size(&self) -> usize {
+        usize::try_from(self.max_size / 8).unwrap_or(usize::MAX)
+    }
+
+    /// Data stored under `key`, `None` also when the file was removed behind the cache's back
+    pub async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let hash = hash(key);
+        let indexed = {
+            let mut index = self.index();
+            match index.sizes.contains_key(&hash) {
+                true => {
+                    index.recency.touch(&hash);
+                    true
+                }
+                false if index.scanned => return None,
+                false => false,
+            }
+        };
+        let data = match tokio::fs::read(self.path(hash)).await {
+            Ok(data) => data,
+            Err(_) => {
+                self.index().remove(hash);
+                return None;
+            }
+        };
+        // not written by a cache, left alone and out of the index
+        if !data.starts_with(MAGIC) {
+            return None;
+        }
+        if !indexed {
+            // found ahead of the startup scan
+            self.index().add(hash, data.len() as u64);
+        }
+        let data = data.strip_prefix(MAGIC)?;
+        let (length, data) = data.split_at(data.iter().position(|b| *b == b'\n')?);
+        let length: usize = std::str::from_utf8(length).ok()?.parse().ok()?;
+        // a different key with the same hash
+        let stored_key = data.get(1..1 + length)?;
+        if stored_key != key.as_bytes() {
+            return None;
+        }
+        Some(data[1 + length..].to_vec())
+    }
+
+    /// Writes `data` under `key`, replacing what was there, then evicts down to the max size.
+    /// Returns false when the entry is too large to be written.
+    pub async fn insert(&self, key: &str, data: &[u8]) -> Result<bool, Error> {
+        let mut file = MAGIC.to_vec();
+        file.extend_from_slice(format!("{}\n", key.len()).as_bytes());
+        file.extend_from_slice(key.as_bytes());
+        file.extend_from_slice(data);
+        if file.len() > self.max_entry_size() {
+            return Ok(false);
+        }
+
+        let hash = hash(key);
+        let path = self.path(hash);
+        let temp = self.temp.join(format!(
+            "{:016x}.{}",
+            hash,
+            self.writes.fetch_add(1, Ordering::Relaxed)
+        ));
+        tokio::fs::write(&temp, &file).await?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(&temp, &path).await?;
+
+        let victims = {
+            let mut index = self.index();
+            let replaced = index.sizes.insert(hash, file.len() as u64).unwrap_or(0);
+            index.size = index.size - replaced + file.len() as u64;
+            index.recency.touch(&hash);
+            index.evict(self.max_size)
+        };
+        for victim in victims {
+            let _ = tokio::fs::remove_file(self.path(victim)).await;
+        }
+        Ok(true)
+    }
+
+    /// Deletes the file stored under `key`
+    pub async fn remove(&self, key: &str) {
+        let hash = hash(key);
+        self.index().remove(hash);
+        let _ = tokio::fs::remove_file(self.path(hash)).await;
+    }
+
+    fn path(&self, hash: u64) -> PathBuf {
+        path(&self.directory, hash)
+    }
+
+    fn index(&self) -> MutexGuard<'_, DiskIndex> {
+        self.index.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for DiskCache {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.temp);
+    }
+}
+
+impl DiskIndex {
+    fn add(&mut self, hash: u64, size: u64) {
+        if self.sizes.insert(hash, size).is_none() {
+            self.size += size;
+            self.recency.touch(&hash);
+        }
+    }
+
+    /// Adds the files found by the startup scan behind the entries used meanwhile,
+    /// returns what has to go to fit `max_size`
+    fn merge(&mut self, files: Vec<(SystemTime, u64, u64)>, max_size: u64) -> Vec<u64> {
+        let mut used = Vec::new();
+        while let Some(hash) = self.recency.pop() {
+            used.push(hash);
+        }
+        for (_, hash, size) in files {
+            self.add(hash, size);
+        }
+        for hash in used.iter().rev() {
+            self.recency.touch(hash);
+        }
+        self.scanned = true;
+        self.evict(max_size)
+    }
+
+    fn remove(&mut self, hash: u64) {
+        if let Some(size) = self.sizes.remove(&hash) {
+            self.size -= size;
+            self.recency.remove(&hash);
+        }
+    }
+
+    /// Forgets least recently used entries until `max_size` is met, returns them
+    fn evict(&mut self, max_size: u64) -> Vec<u64> {
+        let mut victims = Vec::new();
+        while self.size > max_size {
+            let Some(victim) = self.recency.pop() else {
+                break;
+            };
+            self.size -= self.sizes.remove(&victim).unwrap_or(0);
+            victims.push(victim);
+        }
+        victims
+    }
+}
+
+fn path(directory: &Path, hash: u64) -> PathBuf {
+    let name = format!("{:016x}", hash);
+    directory.join(&name[15..]).join(&name[13..15]).join(name)
+}
+
+/// Cache files under `directory` with their mtime, hash and size, oldest first.
+/// Whatever can't be read or doesn't start with `MAGIC` is skipped, so are temp files of earlier runs,
+/// which are removed.
+fn scan(directory: &Path) -> Vec<(SystemTime, u64, u64)> {
+    let own_prefix = format!("{}.", std::process::id());
+    let temps = fs::read_dir(directory.join(TEMP_DIRECTORY))
+        .into_iter()
+        .flatten()
+        .flatten();
+    for temp in temps {
+        if !temp.file_name().to_string_lossy().starts_with(&own_prefix) {
+            let _ = fs::remove_dir_all(temp.path());
+        }
+    }
+
+    let directories = |path: PathBuf| {
+        fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_name() != TEMP_DIRECTORY)
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+    };
+    let mut files = Vec::new();
+    for first in directories(directory.to_path_buf()) {
+        for second in directories(first.path()) {
+            for file in fs::read_dir(second.path()).into_iter().flatten().flatten() {
+                let name = file.file_name();
+                let Some(hash) = name
+                    .to_str()
+                    .filter(|name| name.len() == 16)
+                    .and_then(|name| u64::from_str_radix(name, 16).ok())
+                else {
+                    continue;
+                };
+                // evicted or replaced since it was listed
+                let Ok(metadata) = file.metadata() else {
+                    continue;
+                };
+                if !has_magic(&file.path()) {
+                    eprintln!(
+                        "Disk cache: leaving {:?} alone, it isn't a cache file",
+                        file.path()
+                    );
+                    continue;
+                }
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((modified, hash, metadata.len()));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Whether the file starts like one this cache wrote, others under the directory are never removed
+fn has_magic(path: &Path) -> bool {
+    let mut header = [0; MAGIC.len()];
+    fs::File::open(path).is_ok_and(|mut file| file.read_exact(&mut header).is_ok())
+        && header == MAGIC
+}
+
+/// FNV-1a, stable across runs and Rust versions unlike the std hasher
+fn hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn scanned(cache: &DiskCache) {
+        while !cache.index().scanned {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache() {
+        let directory =
+            std::env::temp_dir().join(format!("rs-ngnix-disk-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let cache = DiskCache::new(&directory, 8 * 1024).unwrap();
+        scanned(&cache).await;
+        assert!(cache.insert("a", b"first").await.unwrap());
+        assert!(cache.insert("a", b"second").await.unwrap());
+        assert!(cache.insert("b", &[0; 900]).await.unwrap());
+        assert!(!cache.insert("c", &[0; 2048]).await.unwrap());
+        assert_eq!(cache.get("a").await.unwrap(), b"second");
+        assert!(cache.get("c").await.is_none());
+        let name = format!("{:016x}", hash("a"));
+        assert!(
+            directory
+                .join(&name[15..])
+                .join(&name[13..15])
+                .join(&name)
+                .is_file()
+        );
+
+        // a restart finds both entries, then the least recently read go first
+        drop(cache);
+        let cache = DiskCache::new(&directory, 8 * 1024).unwrap();
+        scanned(&cache).await;
+        assert_eq!(cache.get("a").await.unwrap(), b"second");
+        assert_eq!(cache.get("b").await.unwrap().len(), 900);
+        for key in 0..10 {
+            cache.insert(&key.to_string(), &[0; 900]).await.unwrap();
+        }
+        assert!(cache.get("a").await.is_none());
+        assert!(cache.get("9").await.is_some());
+        assert!(cache.index().size <= 8 * 1024);
+
+        // entries are found before the scan is done, which only removes temp files of earlier runs
+        drop(cache);
+        let earlier = directory.join(TEMP_DIRECTORY).join("0.0");
+        fs::create_dir_all(&earlier).unwrap();
+        let other = DiskCache::new(&directory, 8 * 1024).unwrap();
+        fs::write(other.temp.join("partial"), b"").unwrap();
+        let cache = DiskCache::new(&directory, 8 * 1024).unwrap();
+        assert!(cache.get("9").await.is_some());
+        scanned(&cache).await;
+        assert!(!earlier.exists());
+        assert!(other.temp.join("partial").is_file());
+        drop(other);
+        drop(cache);
+
+        // files with a cache name but not in the cache's format are neither indexed nor evicted
+        let foreign = path(&directory, hash("foreign"));
+        fs::create_dir_all(foreign.parent().unwrap()).unwrap();
+        fs::write(&foreign, [0; 4096]).unwrap();
+        let cache = DiskCache::new(&directory, 4 * 1024).unwrap();
+        assert!(cache.get("foreign").await.is_none());
+        scanned(&cache).await;
+        for key in 0..10 {
+            cache.insert(&key.to_string(), &[0; 400]).await.unwrap();
+        }
+        assert!(cache.index().size <= 4 * 1024);
+        assert!(!cache.index().sizes.contains_key(&hash("foreign")));
+        assert_eq!(fs::read(&foreign).unwrap(), [0; 4096]);
+        drop(cache);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
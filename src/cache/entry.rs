@@ -0,0 +1,89 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    cache::sharded::{Cache, Weight},
+    compression::encoder::Encoding,
+    handler::conditional::Validators,
+    http_parser::headers::Headers,
+};
+
+/// Cache of static file responses
+pub type FileCache = Cache<CacheKey, CacheEntry>;
+
+/// Each encoding of a file is cached on its own
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub path: PathBuf,
+    pub encoding: Encoding,
+}
+
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub data: Arc<[u8]>,
+    pub headers: Headers, // of a full response: type, encoding, validators
+    pub validators: Validators,
+    pub modified: Option<SystemTime>, // of the file the entry was made from
+    pub validated: Mutex<Instant>,    // when `modified` was last compared with the file
+}
+
+impl CacheEntry {
+    /// Whether the file was compared with the entry less than `valid` ago
+    pub fn is_fresh(&self, valid: Duration) -> bool {
+        self.validated().elapsed() < valid
+    }
+
+    /// Records a comparison with the file, without reinserting the entry and resetting its ttl
+    pub fn revalidated(&self) {
+        *self
+            .validated
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    fn validated(&self) -> Instant {
+        *self
+            .validated
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for CacheEntry {
+    fn default() -> CacheEntry {
+        CacheEntry {
+            data: Arc::from([]),
+            headers: Headers::new(),
+            validators: Validators::default(),
+            modified: None,
+            validated: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl Weight for CacheEntry {
+    fn weight(&self) -> usize {
+        self.data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_fresh() {
+        let valid = Duration::from_secs(60);
+        let entry = CacheEntry {
+            validated: Mutex::new(Instant::now() - valid),
+            ..CacheEntry::default()
+        };
+        assert!(!entry.is_fresh(valid));
+        assert!(entry.is_fresh(valid * 2));
+        entry.revalidated();
+        assert!(entry.is_fresh(valid));
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+use crate::cache::{lru::Lru, policy::Policy};
+
+/// Evicts the least frequently used key, the least recently used among equals
+pub struct Lfu<K> {
+    counts: HashMap<K, u64>,
+    buckets: BTreeMap<u64, Lru<K>>, // keys by use count
+}
+
+impl<K: Hash + Eq + Clone> Lfu<K> {
+    pub fn new() -> Lfu<K> {
+        Lfu {
+            counts: HashMap::new(),
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    fn unlink(&mut self, key: &K, count: u64) {
+        if let Some(bucket) = self.buckets.get_mut(&count) {
+            bucket.remove(key);
+            if bucket.is_empty() {
+                self.buckets.remove(&count);
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send> Policy<K> for Lfu<K> {
+    fn touch(&mut self, key: &K) {
+        let Some(count) = self.counts.get_mut(key) else {
+            return;
+        };
+        *count += 1;
+        let count = *count;
+        self.unlink(key, count - 1);
+        self.buckets
+            .entry(count)
+            .or_insert_with(Lru::new)
+            .touch(key);
+    }
+
+    fn insert(&mut self, key: &K) {
+        if self.counts.contains_key(key) {
+            return self.touch(key);
+        }
+        self.counts.insert(key.clone(), 1);
+        self.buckets.entry(1).or_insert_with(Lru::new).touch(key);
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(count) = self.counts.remove(key) {
+            self.unlink(key, count);
+        }
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        let mut bucket = self.buckets.first_entry()?;
+        let key = bucket.get_mut().pop()?;
+        if bucket.get().is_empty() {
+            bucket.remove();
+        }
+        self.counts.remove(&key);
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_frequent() {
+        let mut lfu = Lfu::new();
+        for key in ["hot", "warm", "cold"] {
+            lfu.insert(&key);
+        }
+        lfu.touch(&"hot");
+        lfu.touch(&"hot");
+        lfu.touch(&"warm");
+        lfu.touch(&"cold");
+        lfu.touch(&"warm");
+
+        assert_eq!(lfu.evict(), Some("cold"));
+        assert_eq!(lfu.evict(), Some("hot"));
+        assert_eq!(lfu.evict(), Some("warm"));
+        assert_eq!(lfu.evict(), None);
+    }
+}
@@ -1,106 +1,148 @@
-use std::{
-    collections::HashMap,
-    path::PathBuf,
-    sync::{Arc, Weak},
-};
+use std::{collections::HashMap, hash::Hash};
 
-use tokio::sync::RwLock;
+use crate::cache::policy::Policy;
 
-use crate::cache::util::{add_after_head, move_node_to_head, purge};
-
-#[derive(Default, Debug)]
-pub struct CacheEntry {
-    pub data: Vec<u8>,
-}
-
-#[derive(Default, Debug)]
-pub struct CacheList {
-    pub key: PathBuf,
-    pub cache_entry: CacheEntry,
-    pub next: Option<Arc<RwLock<CacheList>>>,
-    pub prev: Option<Weak<RwLock<CacheList>>>,
+/// Keys from most to least recently used, in a slab backed list so every operation is O(1)
+#[derive(Debug)]
+pub struct Lru<K> {
+    slots: HashMap<K, usize>,
+    nodes: Vec<Option<Node<K>>>,
+    free: Vec<usize>,
+    head: Option<usize>, // most recently used
+    tail: Option<usize>, // least recently used
 }
 
-pub struct Cache {
-    pub cache_map: RwLock<HashMap<PathBuf, Weak<RwLock<CacheList>>>>,
-    pub cache_ll_head: Arc<RwLock<CacheList>>,
-    pub cache_ll_tail: Arc<RwLock<CacheList>>,
-    pub capacity: usize,          //in b
-    pub data_size: RwLock<usize>, // in b
+#[derive(Debug)]
+struct Node<K> {
+    key: K,
+    prev: Option<usize>,
+    next: Option<usize>,
 }
 
-impl CacheEntry {
-    pub fn new(data: Vec<u8>) -> CacheEntry {
-        CacheEntry { data }
+impl<K: Hash + Eq + Clone> Lru<K> {
+    pub fn new() -> Lru<K> {
+        Lru {
+            slots: HashMap::new(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: None,
+            tail: None,
+        }
     }
-}
 
-impl CacheList {
-    pub fn new(key: PathBuf, cache_entry: CacheEntry) -> CacheList {
-        CacheList {
-            key,
-            cache_entry,
-            next: None,
-            prev: None,
+    /// Marks `key` as the most recently used, adding it when it isn't tracked yet
+    pub fn touch(&mut self, key: &K) {
+        match self.slots.get(key) {
+            Some(&slot) => {
+                self.unlink(slot);
+                self.push_front(slot);
+            }
+            None => {
+                let node = Some(Node {
+                    key: key.clone(),
+                    prev: None,
+                    next: None,
+                });
+                let slot = match self.free.pop() {
+                    Some(slot) => {
+                        self.nodes[slot] = node;
+                        slot
+                    }
+                    None => {
+                        self.nodes.push(node);
+                        self.nodes.len() - 1
+                    }
+                };
+                self.slots.insert(key.clone(), slot);
+                self.push_front(slot);
+            }
         }
     }
-}
 
-impl Cache {
-    pub fn new(capacity: usize) -> Cache {
-        Cache {
-            cache_map: RwLock::new(HashMap::new()),
-            cache_ll_head: Arc::new(RwLock::new(CacheList::default())),
-            cache_ll_tail: Arc::new(RwLock::new(CacheList::default())),
-            capacity: capacity * 1024,
-            data_size: RwLock::new(0),
-        }
+    pub fn len(&self) -> usize {
+        self.slots.len()
     }
-    pub async fn get(&self, key: &PathBuf) -> Option<Vec<u8>> {
-        if self.capacity == 0 {
-            return None;
-        }
-        let cache_map = self.cache_map.read().await;
-        if let Some(weak_cache_ll_entry) = cache_map.get(key).cloned() {
-            drop(cache_map);
-            if let Some(cache_ll_entry) = weak_cache_ll_entry.upgrade() {
-                move_node_to_head(
-                    &self.cache_ll_head,
-                    &self.cache_ll_tail,
-                    &weak_cache_ll_entry,
-                    None,
-                )
-                .await;
-                let node = cache_ll_entry.read().await;
-                return Some(node.cache_entry.data.clone());
-            }
-        }
 
-        None
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.slots.contains_key(key)
+    }
+
+    /// The least recently used key
+    pub fn peek(&self) -> Option<&K> {
+        self.nodes[self.tail?].as_ref().map(|node| &node.key)
     }
-    pub async fn add(&self, key: &PathBuf, data: &Vec<u8>) {
-        if self.capacity == 0 {
-            return;
-        }
 
-        let mut data_size_lock = self.data_size.write().await;
-        *data_size_lock += data.len();
-        drop(data_size_lock);
+    pub fn remove(&mut self, key: &K) {
+        if let Some(slot) = self.slots.remove(key) {
+            self.unlink(slot);
+            self.nodes[slot] = None;
+            self.free.push(slot);
+        }
+    }
 
-        let mut cache_map = self.cache_map.write().await;
+    /// Removes and returns the least recently used key
+    pub fn pop(&mut self) -> Option<K> {
+        let key = self.node(self.tail?).key.clone();
+        self.remove(&key);
+        Some(key)
+    }
 
-        if let Some(node) = cache_map.get(key) {
-            move_node_to_head(&self.cache_ll_head, &self.cache_ll_tail, node, Some(data)).await;
-        } else {
-            let node = CacheList::new(key.to_path_buf(), CacheEntry::new(data.to_vec()));
+    fn node(&mut self, slot: usize) -> &mut Node<K> {
+        self.nodes[slot]
+            .as_mut()
+            .expect("linked slots always hold a node")
+    }
 
-            let arc_node = Arc::new(RwLock::new(node));
+    fn unlink(&mut self, slot: usize) {
+        let (prev, next) = {
+            let node = self.node(slot);
+            (node.prev.take(), node.next.take())
+        };
+        match prev {
+            Some(prev) => self.node(prev).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.node(next).prev = prev,
+            None => self.tail = prev,
+        }
+    }
 
-            cache_map.insert(key.to_path_buf(), Arc::downgrade(&arc_node));
-            drop(cache_map);
+    fn push_front(&mut self, slot: usize) {
+        let head = self.head;
+        {
+            let node = self.node(slot);
+            node.prev = None;
+            node.next = head;
+        }
+        match head {
+            Some(head) => self.node(head).prev = Some(slot),
+            None => self.tail = Some(slot),
+        }
+        self.head = Some(slot);
+    }
+}
 
-            add_after_head(&self.cache_ll_head, &arc_node).await;
-            purge(self).await;
+impl<K: Hash + Eq + Clone + Send> Policy<K> for Lru<K> {
+    fn touch(&mut self, key: &K) {
+        if self.contains(key) {
+            Lru::touch(self, key);
         }
     }
+
+    fn insert(&mut self, key: &K) {
+        Lru::touch(self, key);
+    }
+
+    fn remove(&mut self, key: &K) {
+        Lru::remove(self, key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        self.pop()
+    }
 }
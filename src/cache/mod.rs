@@ -1,3 +1,13 @@
-pub mod lru;
+mod arc;
+pub mod control;
+mod disk;
+pub mod entry;
+mod lfu;
+mod lru;
+pub mod policy;
+pub mod proxy;
+pub mod sharded;
+pub mod sweeper;
 pub mod test;
-mod util;
+mod tinylfu;
+pub mod watcher;
@@ -0,0 +1,51 @@
+use std::{hash::Hash, io::Error};
+
+use crate::cache::{arc::AdaptiveReplacement, lfu::Lfu, lru::Lru, tinylfu::TinyLfu};
+
+/// Decides which key a full cache shard gives up. Policies only see keys,
+/// the shard evicts until the entries' weights fit its capacity again.
+pub trait Policy<K>: Send {
+    /// A cached key was read
+    fn touch(&mut self, key: &K);
+    /// A key that isn't cached was looked up
+    fn miss(&mut self, _key: &K) {}
+    /// A key was added to the cache
+    fn insert(&mut self, key: &K);
+    /// A key left the cache without being evicted
+    fn remove(&mut self, key: &K);
+    /// Picks and forgets the next key to evict, possibly the one just inserted
+    fn evict(&mut self) -> Option<K>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PolicyKind {
+    #[default]
+    Lru,
+    Lfu,
+    TinyLfu,
+    Arc,
+}
+
+impl PolicyKind {
+    pub fn parse(name: &str) -> Result<PolicyKind, Error> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "lru" => Ok(PolicyKind::Lru),
+            "lfu" => Ok(PolicyKind::Lfu),
+            "w-tinylfu" | "tinylfu" => Ok(PolicyKind::TinyLfu),
+            "arc" => Ok(PolicyKind::Arc),
+            _ => Err(Error::other(format!(
+                "Invalid cache_policy {}: expected lru, lfu, w-tinylfu or arc",
+                name
+            ))),
+        }
+    }
+
+    pub fn build<K: Hash + Eq + Clone + Send + 'static>(&self) -> Box<dyn Policy<K>> {
+        match self {
+            PolicyKind::Lru => Box::new(Lru::new()),
+            PolicyKind::Lfu => Box::new(Lfu::new()),
+            PolicyKind::TinyLfu => Box::new(TinyLfu::new()),
+            PolicyKind::Arc => Box::new(AdaptiveReplacement::new()),
+        }
+    }
+}
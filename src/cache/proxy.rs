@@ -0,0 +1,635 @@
+use std::{
+    io::Error,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    cache::{
+        control::{Freshness, storable},
+        disk::DiskCache,
+        policy::PolicyKind,
+        sharded::{Cache, Weight},
+        sweeper::sweep_expired,
+    },
+    config::UpstreamConfig,
+    http_parser::{
+        headers::Headers,
+        request::{Request, Version},
+        response::ResponseHead,
+    },
+};
+
+pub const DEFAULT_PROXY_CACHE_KEY: &str = "$scheme$host$request_uri";
+pub const DEFAULT_PROXY_CACHE_INACTIVE: u64 = 600; // in s, like nginx
+pub const DEFAULT_PROXY_CACHE_MAX_SIZE: u64 = 1024; // in MB
+
+/// Upstream responses of a proxy, looked up by the expanded `proxy_cache_key`.
+/// With `proxy_cache_path`, every entry is also written to disk and read back when memory lost it.
+pub struct ProxyCache {
+    responses: Arc<Cache<String, ProxyEntry>>,
+    disk: Option<Box<DiskCache>>, // boxed, proxies without one stay small
+    key: CacheKeyTemplate,
+}
+
+/// A response, or the request headers its variants are told apart by when it had `Vary`
+#[derive(Clone)]
+enum ProxyEntry {
+    Response(Arc<CachedResponse>),
+    Variants(Vec<String>),
+}
+
+/// A stored upstream response with its body
+#[derive(Debug)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub reason: String,
+    pub headers: Headers, // as received, with a `Content-Length` instead of chunked framing
+    pub body: Vec<u8>,
+    freshness: Freshness,
+    received: Instant,
+}
+
+/// Value of the `X-Cache-Status` header sent with responses of a cached proxy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheStatus {
+    Miss,
+    Hit,
+    Expired,     // a stored response was too old, this one is new from the upstream
+    Stale,       // a too old response, served because the upstream failed
+    Revalidated, // a too old response the upstream confirmed with a 304
+}
+
+/// `proxy_cache_key` split into text and the variables filled in per request
+#[derive(Debug, PartialEq)]
+struct CacheKeyTemplate {
+    parts: Vec<KeyPart>,
+}
+
+#[derive(Debug, PartialEq)]
+enum KeyPart {
+    Text(String),
+    Scheme,
+    Host,
+    RequestUri,
+    Uri,
+    Args,
+    RequestMethod,
+    Header(String), // `$http_user_agent` is the `User-Agent` header
+}
+
+impl ProxyCache {
+    /// `None` when `proxy_cache` isn't set
+    pub fn new(config: &UpstreamConfig) -> Result<Option<ProxyCache>, Error> {
+        let capacity = match config.proxy_cache {
+            Some(size) if size > 0 => size * 1024,
+            _ => return Ok(None),
+        };
+        let key = CacheKeyTemplate::parse(
+            config
+                .proxy_cache_key
+                .as_deref()
+                .unwrap_or(DEFAULT_PROXY_CACHE_KEY),
+        )?;
+        let inactive = config
+            .proxy_cache_inactive
+            .unwrap_or(DEFAULT_PROXY_CACHE_INACTIVE);
+        // same split as the file cache, no single response takes more than a shard
+        let responses = Arc::new(
+            Cache::new(capacity, capacity / 8, PolicyKind::default())
+                .with_expiry(None, Some(Duration::from_secs(inactive))),
+        );
+        sweep_expired(&responses);
+        let disk = match &config.proxy_cache_path {
+            Some(directory) => Some(Box::new(DiskCache::new(
+                Path::new(directory),
+                config
+                    .proxy_cache_max_size
+                    .unwrap_or(DEFAULT_PROXY_CACHE_MAX_SIZE)
+                    * 1024
+                    * 1024,
+            )?)),
+            None => None,
+        };
+        Ok(Some(ProxyCache {
+            responses,
+            disk,
+            key,
+        }))
+    }
+
+    /// Checks the settings `new` would use, without starting anything
+    pub fn validate(config: &UpstreamConfig) -> Result<(), Error> {
+        CacheKeyTemplate::parse(
+            config
+                .proxy_cache_key
+                .as_deref()
+                .unwrap_or(DEFAULT_PROXY_CACHE_KEY),
+        )?;
+        if config.proxy_cache_inactive == Some(0) {
+            return Err(Error::other(
+                "Invalid proxy_cache_inactive: expected at least 1 second",
+            ));
+        }
+        if config.proxy_cache_max_size == Some(0) {
+            return Err(Error::other(
+                "Invalid proxy_cache_max_size: expected at least 1 MB",
+            ));
+        }
+        if config.proxy_cache_path.is_some() && config.proxy_cache.unwrap_or(0) == 0 {
+            return Err(Error::other(
+                "Invalid proxy_cache_path: needs proxy_cache for the memory tier",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn key(&self, request: &Request, scheme: &str) -> String {
+        self.key.expand(request, scheme)
+    }
+
+    /// Largest body worth collecting while it is streamed to the client
+    pub fn max_entry_size(&self) -> usize {
+        let disk = self.disk.as_ref().map_or(0, |disk| disk.max_entry_size());
+        self.responses.max_entry_size().max(disk)
+    }
+
+    /// Stored response under `key` matching the `Vary` headers of `request`, fresh or not
+    pub async fn get(&self, key: &str, request: &Request) -> Option<Arc<CachedResponse>> {
+        let entry = self.entry(key).await?;
+        let entry = match &*entry {
+            ProxyEntry::Response(response) => return Some(response.clone()),
+            ProxyEntry::Variants(vary) => self.entry(&variant_key(key, vary, request)).await?,
+        };
+        match &*entry {
+            ProxyEntry::Response(response) => Some(response.clone()),
+            ProxyEntry::Variants(_) => None,
+        }
+    }
+
+    /// Looks in memory, then on disk. Entries found on disk are promoted to memory.
+    async fn entry(&self, key: &str) -> Option<Arc<ProxyEntry>> {
+        let key = key.to_string();
+        if let Some(entry) = self.responses.get(&key) {
+            return Some(entry);
+        }
+        let disk = self.disk.as_ref()?;
+        let entry = ProxyEntry::decode(&disk.get(&key).await?)?;
+        self.responses.insert(key, entry.clone());
+        Some(Arc::new(entry))
+    }
+
+    pub async fn insert(&self, key: &str, request: &Request, response: CachedResponse) {
+        let vary: Vec<String> = response
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("vary"))
+            .flat_map(|(_, value)| value.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        let response = ProxyEntry::Response(Arc::new(response));
+        if vary.is_empty() {
+            self.store(key.to_string(), response).await;
+            return;
+        }
+        self.store(variant_key(key, &vary, request), response).await;
+        self.store(key.to_string(), ProxyEntry::Variants(vary))
+            .await;
+    }
+
+    /// Drops the response `get` would find for `request`, from memory and disk
+    pub async fn remove(&self, key: &str, request: &Request) {
+        let key = match self.entry(key).await.as_deref() {
+            Some(ProxyEntry::Variants(vary)) => variant_key(key, vary, request),
+            _ => key.to_string(),
+        };
+        self.responses.remove(&key);
+        if let Some(disk) = &self.disk {
+            disk.remove(&key).await;
+        }
+    }
+
+    /// Writes through to disk, which may take entries too large for memory
+    async fn store(&self, key: String, entry: ProxyEntry) {
+        if let Some(disk) = &self.disk
+            && let Err(e) = disk.insert(&key, &entry.encode()).await
+        {
+            eprintln!("Failed to write {} to the disk cache: {}", key, e);
+        }
+        self.responses.insert(key, entry);
+    }
+}
+
+impl ProxyEntry {
+    /// A line describing the entry, then for a response its headers, an empty line and the body
+    fn encode(&self) -> Vec<u8> {
+        let response = match self {
+            ProxyEntry::Variants(vary) => {
+                return format!("variants {}\n", vary.join(",")).into_bytes();
+            }
+            ProxyEntry::Response(response) => response,
+        };
+        let received = SystemTime::now() - response.received.elapsed();
+        let mut data = format!(
+            "response {} {} {} {} {} {}\n",
+            response.status,
+            received
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |received| received.as_secs()),
+            response.freshness.lifetime.as_secs(),
+            response.freshness.initial_age.as_secs(),
+            response.freshness.must_revalidate as u8,
+            response.reason
+        );
+        for (name, value) in response.headers.iter() {
+            data.push_str(&format!("{}: {}\n", name, value));
+        }
+        data.push('\n');
+        let mut data = data.into_bytes();
+        data.extend_from_slice(&response.body);
+        data
+    }
+
+    fn decode(data: &[u8]) -> Option<ProxyEntry> {
+        let mut lines = data.split(|b| *b == b'\n');
+        let description = std::str::from_utf8(lines.next()?).ok()?;
+        if let Some(vary) = description.strip_prefix("variants ") {
+            return Some(ProxyEntry::Variants(
+                vary.split(',').map(String::from).collect(),
+            ));
+        }
+
+        let mut fields = description.strip_prefix("response ")?.splitn(6, ' ');
+        let mut number = || fields.next()?.parse::<u64>().ok();
+        let (status, received, lifetime, initial_age, must_revalidate) =
+            (number()?, number()?, number()?, number()?, number()?);
+        let reason = fields.next().unwrap_or("").to_string();
+        let mut headers = Headers::new();
+        let mut offset = description.len() + 1;
+        for line in lines {
+            offset += line.len() + 1;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = std::str::from_utf8(line).ok()?.split_once(':')?;
+            headers.append(name, value.trim_start());
+        }
+
+        // the time spent on disk counts towards the age, out of range times mean a damaged file
+        let received = UNIX_EPOCH.checked_add(Duration::from_secs(received))?;
+        let stored_for = SystemTime::now()
+            .duration_since(received)
+            .unwrap_or(Duration::ZERO);
+        let initial_age = Duration::from_secs(initial_age).checked_add(stored_for)?;
+        Some(ProxyEntry::Response(Arc::new(CachedResponse {
+            status: u16::try_from(status).ok()?,
+            reason,
+            headers,
+            body: data.get(offset..)?.to_vec(),
+            freshness: Freshness {
+                lifetime: Duration::from_secs(lifetime),
+                initial_age,
+                must_revalidate: must_revalidate == 1,
+            },
+            received: Instant::now(),
+        })))
+    }
+}
+
+impl Weight for ProxyEntry {
+    fn weight(&self) -> usize {
+        match self {
+            ProxyEntry::Response(response) => {
+                let headers: usize = response
+                    .headers
+                    .iter()
+                    .map(|(name, value)| name.len() + value.len())
+                    .sum();
+                response.body.len() + headers
+            }
+            ProxyEntry::Variants(vary) => vary.iter().map(String::len).sum(),
+        }
+    }
+}
+
+/// Key of the variant selected by the request's values of the `vary` headers
+fn variant_key(key: &str, vary: &[String], request: &Request) -> String {
+    let mut variant = key.to_string();
+    for name in vary {
+        let values: Vec<&str> = request
+            .headers
+            .iter()
+            .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+            .collect();
+        variant.push('\n');
+        variant.push_str(&values.join(", "));
+    }
+    variant
+}
+
+impl CachedResponse {
+    pub fn new(response: &ResponseHead, body: Vec<u8>, freshness: Freshness) -> CachedResponse {
+        let mut headers = response.headers.clone();
+        headers.remove("transfer-encoding");
+        headers.remove("age");
+        if response.status != 204 && response.status != 304 {
+            headers.set("Content-Length", &body.len().to_string());
+        }
+        CachedResponse {
+            status: response.status,
+            reason: response.reason.clone(),
+            headers,
+            body,
+            freshness,
+            received: Instant::now(),
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        // `Age` comes from the upstream or a file on disk, any value is possible
+        self.freshness
+            .initial_age
+            .saturating_add(self.received.elapsed())
+    }
+
+    pub fn is_fresh(&self) -> bool {
+        self.age() < self.freshness.lifetime
+    }
+
+    /// Whether a stale copy may stand in for an upstream that failed (RFC 9111 section 4.2.4)
+    pub fn serves_stale(&self) -> bool {
+        !self.freshness.must_revalidate
+    }
+
+    /// Conditional headers asking the upstream whether the stored response is still current
+    pub fn conditional_headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        if let Some(etag) = self.headers.get("etag") {
+            headers.append("If-None-Match", etag);
+        }
+        if let Some(modified) = self.headers.get("last-modified") {
+            headers.append("If-Modified-Since", modified);
+        }
+        headers
+    }
+
+    /// The stored response updated with the headers of a `304 Not Modified` (RFC 9111 section 4.3.4),
+    /// `None` when they no longer allow storing it
+    pub fn revalidated(
+        &self,
+        request: &Request,
+        not_modified: &ResponseHead,
+    ) -> Option<CachedResponse> {
+        let mut headers = self.headers.clone();
+        for (name, _) in not_modified.headers.iter() {
+            if !name.eq_ignore_ascii_case("content-length") {
+                headers.remove(name);
+            }
+        }
+        for (name, value) in not_modified.headers.iter() {
+            if !name.eq_ignore_ascii_case("content-length") {
+                headers.append(name, value);
+            }
+        }
+        let head = ResponseHead {
+            version: Version::Http11,
+            status: self.status,
+            reason: self.reason.clone(),
+            headers,
+        };
+        let freshness = storable(request, &head)?;
+        Some(CachedResponse::new(&head, self.body.clone(), freshness))
+    }
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Expired => "EXPIRED",
+            CacheStatus::Stale => "STALE",
+            CacheStatus::Revalidated => "REVALIDATED",
+        }
+    }
+}
+
+impl CacheKeyTemplate {
+    fn parse(template: &str) -> Result<CacheKeyTemplate, Error> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('$') {
+            if start > 0 {
+                parts.push(KeyPart::Text(rest[..start].to_string()));
+            }
+            let name_length = rest[1 + start..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len() - start - 1);
+            let name = &rest[1 + start..1 + start + name_length];
+            parts.push(match name {
+                // a `$` not followed by a name is kept as is
+                "" => KeyPart::Text("$".to_string()),
+                "scheme" => KeyPart::Scheme,
+                "host" => KeyPart::Host,
+                "request_uri" => KeyPart::RequestUri,
+                "uri" => KeyPart::Uri,
+                "args" => KeyPart::Args,
+                "request_method" => KeyPart::RequestMethod,
+                _ => match name.strip_prefix("http_") {
+                    Some(header) if !header.is_empty() => KeyPart::Header(header.replace('_', "-")),
+                    _ => {
+                        return Err(Error::other(format!(
+                            "Invalid proxy_cache_key {}: unknown variable ${}",
+                            template, name
+                        )));
+                    }
+                },
+            });
+            rest = &rest[1 + start + name_length..];
+        }
+        if !rest.is_empty() {
+            parts.push(KeyPart::Text(rest.to_string()));
+        }
+        Ok(CacheKeyTemplate { parts })
+    }
+
+    fn expand(&self, request: &Request, scheme: &str) -> String {
+        let mut key = String::new();
+        for part in &self.parts {
+            match part {
+                KeyPart::Text(text) => key.push_str(text),
+                KeyPart::Scheme => key.push_str(scheme),
+                KeyPart::Host => {
+                    // without the port, case-insensitive like the name it stands for
+                    let host = request.headers.get("host").unwrap_or("");
+                    let host = match host.rsplit_once(':') {
+                        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+                        _ => host,
+                    };
+                    key.push_str(&host.to_ascii_lowercase());
+                }
+                KeyPart::RequestUri => key.push_str(&request.target),
+                KeyPart::Uri => key.push_str(&request.uri),
+                KeyPart::Args => key.push_str(request.query().unwrap_or("")),
+                KeyPart::RequestMethod => key.push_str(&request.method),
+                KeyPart::Header(name) => key.push_str(request.headers.get(name).unwrap_or("")),
+            }
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_parser::{request::parse_request, response::parse_response};
+
+    fn request(headers: &str) -> Request {
+        let head = format!(
+            "GET /a?b=1 HTTP/1.1\r\nHost: Example.com:8080\r\n{}\r\n",
+            headers
+        );
+        parse_request(head.as_bytes()).unwrap()
+    }
+
+    fn response(headers: &str, body: &[u8]) -> CachedResponse {
+        let head = format!("HTTP/1.1 200 OK\r\n{}\r\n", headers);
+        let head = parse_response(head.as_bytes()).unwrap();
+        let freshness = storable(&request(""), &head).unwrap();
+        CachedResponse::new(&head, body.to_vec(), freshness)
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let key = |template: &str| {
+            CacheKeyTemplate::parse(template)
+                .unwrap()
+                .expand(&request("User-Agent: curl\r\n"), "https")
+        };
+        assert_eq!(key(DEFAULT_PROXY_CACHE_KEY), "httpsexample.com/a?b=1");
+        assert_eq!(
+            key("$request_method:$uri|$args|$http_user_agent$"),
+            "GET:/a|b=1|curl$"
+        );
+        assert!(CacheKeyTemplate::parse("$scheme$cookie_id").is_err());
+        assert!(CacheKeyTemplate::parse("$http_").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_variants() {
+        let config: UpstreamConfig = serde_yaml::from_str("proxy_cache: 1024").unwrap();
+        let cache = ProxyCache::new(&config).unwrap().unwrap();
+        let gzip = request("Accept-Encoding: gzip\r\n");
+        let br = request("Accept-Encoding: br\r\n");
+        let vary = "Cache-Control: max-age=60\r\nVary: Accept-Encoding\r\n";
+        cache.insert("k", &gzip, response(vary, b"gzip")).await;
+        cache.insert("k", &br, response(vary, b"br")).await;
+        cache
+            .insert(
+                "plain",
+                &gzip,
+                response("Cache-Control: max-age=60\r\n", b"plain"),
+            )
+            .await;
+
+        assert_eq!(cache.get("k", &gzip).await.unwrap().body, b"gzip");
+        assert_eq!(cache.get("k", &br).await.unwrap().body, b"br");
+        assert!(cache.get("k", &request("")).await.is_none());
+        assert_eq!(cache.get("plain", &br).await.unwrap().body, b"plain");
+        assert_eq!(
+            cache
+                .get("plain", &br)
+                .await
+                .unwrap()
+                .headers
+                .get("content-length"),
+            Some("5")
+        );
+    }
+
+    #[test]
+    fn test_decode_damaged_entries() {
+        let huge = format!("response 200 {} 60 0 0 OK\n\n", u64::MAX);
+        assert!(ProxyEntry::decode(huge.as_bytes()).is_none());
+        let aged = format!("response 200 0 60 {} 0 OK\n\n", u64::MAX);
+        assert!(ProxyEntry::decode(aged.as_bytes()).is_none());
+        assert!(ProxyEntry::decode(b"response 200 x 60 0 0 OK\n\n").is_none());
+
+        let response = response(
+            &format!("Cache-Control: max-age=60\r\nAge: {}\r\n", u64::MAX),
+            b"",
+        );
+        assert!(!response.is_fresh());
+    }
+
+    #[tokio::test]
+    async fn test_disk_tier() {
+        let directory =
+            std::env::temp_dir().join(format!("rs-ngnix-proxy-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let config: UpstreamConfig = serde_yaml::from_str(&format!(
+            "proxy_cache: 1024\nproxy_cache_path: {:?}",
+            directory
+        ))
+        .unwrap();
+        let gzip = request("Accept-Encoding: gzip\r\n");
+        let vary =
+            "Cache-Control: max-age=60, must-revalidate\r\nVary: Accept-Encoding\r\nAge: 5\r\n";
+        let cache = ProxyCache::new(&config).unwrap().unwrap();
+        cache.insert("k", &gzip, response(vary, b"gzip")).await;
+        drop(cache);
+
+        // a new memory tier, as after a reload, finds the response on disk
+        let cache = ProxyCache::new(&config).unwrap().unwrap();
+        let stored = cache.get("k", &gzip).await.unwrap();
+        assert_eq!(stored.status, 200);
+        assert_eq!(stored.reason, "OK");
+        assert_eq!(stored.body, b"gzip");
+        assert_eq!(stored.headers.get("vary"), Some("Accept-Encoding"));
+        assert!(stored.is_fresh());
+        assert!(!stored.serves_stale());
+        assert!(stored.age() >= Duration::from_secs(5));
+        assert!(cache.get("k", &request("")).await.is_none());
+        assert!(cache.responses.get(&"k".to_string()).is_some());
+
+        // gone from both tiers, the variant list stays
+        cache.remove("k", &gzip).await;
+        assert!(cache.get("k", &gzip).await.is_none());
+        drop(cache);
+        let cache = ProxyCache::new(&config).unwrap().unwrap();
+        assert!(cache.get("k", &gzip).await.is_none());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_revalidated() {
+        let stored = response(
+            "Cache-Control: max-age=0\r\nETag: \"v1\"\r\nX-A: 1\r\n",
+            b"body",
+        );
+        assert!(!stored.is_fresh());
+        assert_eq!(
+            stored.conditional_headers().get("if-none-match"),
+            Some("\"v1\"")
+        );
+
+        let not_modified = parse_response(
+            b"HTTP/1.1 304 Not Modified\r\nCache-Control: max-age=60\r\nContent-Length: 0\r\n\r\n",
+        )
+        .unwrap();
+        let refreshed = stored.revalidated(&request(""), &not_modified).unwrap();
+        assert!(refreshed.is_fresh());
+        assert_eq!(refreshed.body, b"body");
+        assert_eq!(refreshed.headers.get("x-a"), Some("1"));
+        assert_eq!(refreshed.headers.get("content-length"), Some("4"));
+
+        let no_store =
+            parse_response(b"HTTP/1.1 304 Not Modified\r\nCache-Control: no-store\r\n\r\n")
+                .unwrap();
+        assert!(stored.revalidated(&request(""), &no_store).is_none());
+    }
+}
@@ -0,0 +1,387 @@
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hash, RandomState},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::cache::policy::{Policy, PolicyKind};
+
+/// Most shards a cache is split into, more only help with more cores
+const MAX_SHARDS: usize = 16;
+
+/// Values know how many bytes they take up in the cache
+pub trait Weight {
+    fn weight(&self) -> usize;
+}
+
+/// A byte bounded cache split into shards so lookups only contend on one lock.
+/// Each shard is bounded by its share of the capacity and evicts by its own policy.
+pub struct Cache<K, V> {
+    shards: Box<[Mutex<Shard<K, V>>]>,
+    hasher: RandomState,
+    max_entry_size: usize,
+    ttl: Option<Duration>,      // since the entry was inserted
+    inactive: Option<Duration>, // since it was last read
+}
+
+struct Shard<K, V> {
+    entries: HashMap<K, Slot<V>>,
+    policy: Box<dyn Policy<K>>,
+    size: usize,
+    capacity: usize,
+}
+
+struct Slot<V> {
+    value: Arc<V>,
+    weight: usize,
+    expires: Option<Instant>,
+    accessed: Instant,
+}
+
+impl<V> Slot<V> {
+    fn is_expired(&self, now: Instant, inactive: Option<Duration>) -> bool {
+        self.expires.is_some_and(|expires| now >= expires)
+            || inactive.is_some_and(|inactive| now.duration_since(self.accessed) >= inactive)
+    }
+}
+
+impl<K, V> Shard<K, V> {
+    fn remove(&mut self, key: &K) -> bool
+    where
+        K: Hash + Eq,
+    {
+        let Some(slot) = self.entries.remove(key) else {
+            return false;
+        };
+        self.size -= slot.weight;
+        self.policy.remove(key);
+        true
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send + 'static, V: Weight> Cache<K, V> {
+    /// `capacity` and `max_entry_size` are in bytes. There are as many shards as
+    /// max size entries fit, up to `MAX_SHARDS`, so each shard can take one.
+    pub fn new(capacity: usize, max_entry_size: usize, policy: PolicyKind) -> Cache<K, V> {
+        let max_entry_size = max_entry_size.min(capacity);
+        let shards = capacity
+            .checked_div(max_entry_size)
+            .unwrap_or(1)
+            .clamp(1, MAX_SHARDS);
+        Cache::with_shards(capacity, max_entry_size, shards, policy)
+    }
+
+    pub fn with_shards(
+        capacity: usize,
+        max_entry_size: usize,
+        shards: usize,
+        policy: PolicyKind,
+    ) -> Cache<K, V> {
+        let shards = (0..shards)
+            .map(|_| {
+                Mutex::new(Shard {
+                    entries: HashMap::new(),
+                    policy: policy.build(),
+                    size: 0,
+                    capacity: capacity / shards,
+                })
+            })
+            .collect();
+        Cache {
+            shards,
+            hasher: RandomState::new(),
+            max_entry_size,
+            ttl: None,
+            inactive: None,
+        }
+    }
+
+    /// Entries expire `ttl` after being inserted, or when unread for `inactive`
+    pub fn with_expiry(self, ttl: Option<Duration>, inactive: Option<Duration>) -> Cache<K, V> {
+        Cache {
+            ttl,
+            inactive,
+            ..self
+        }
+    }
+
+    /// How often expired entries are worth sweeping, `None` when nothing expires
+    pub fn sweep_interval(&self) -> Option<Duration> {
+        let shortest = self.ttl.into_iter().chain(self.inactive).min()?;
+        Some((shortest / 2).clamp(Duration::from_secs(1), Duration::from_secs(60)))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_entry_size > 0
+    }
+
+    pub fn max_entry_size(&self) -> usize {
+        self.max_entry_size
+    }
+
+    /// Whether an entry of `weight` bytes may be cached, to skip building ones that won't be
+    pub fn fits(&self, weight: u64) -> bool {
+        self.is_enabled() && weight <= self.max_entry_size as u64
+    }
+
+    /// Expired entries are dropped here rather than waiting for the sweeper
+    pub fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.get_at(key, Instant::now())
+    }
+
+    fn get_at(&self, key: &K, now: Instant) -> Option<Arc<V>> {
+        let mut shard = self.shard(key);
+        let expired = match shard.entries.get_mut(key) {
+            Some(slot) if !slot.is_expired(now, self.inactive) => {
+                slot.accessed = now;
+                let value = slot.value.clone();
+                shard.policy.touch(key);
+                return Some(value);
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            shard.remove(key);
+        }
+        shard.policy.miss(key);
+        None
+    }
+
+    /// Adds or replaces an entry and evicts until the shard fits again. Entries over the max size
+    /// or the shard's capacity, or not admitted by the policy, are turned away and `false` is returned.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        self.insert_at(key, value, Instant::now())
+    }
+
+    fn insert_at(&self, key: K, value: V, now: Instant) -> bool {
+        let weight = value.weight();
+        if !self.fits(weight as u64) {
+            return false;
+        }
+        let mut shard = self.shard(&key);
+        if weight > shard.capacity {
+            return false;
+        }
+        let slot = Slot {
+            value: Arc::new(value),
+            weight,
+            expires: self.ttl.map(|ttl| now + ttl),
+            accessed: now,
+        };
+        match shard.entries.insert(key.clone(), slot) {
+            Some(old) => {
+                shard.size -= old.weight;
+                shard.policy.touch(&key);
+            }
+            None => shard.policy.insert(&key),
+        }
+        shard.size += weight;
+        while shard.size > shard.capacity {
+            let Some(victim) = shard.policy.evict() else {
+                break;
+            };
+            if let Some(evicted) = shard.entries.remove(&victim) {
+                shard.size -= evicted.weight;
+            }
+        }
+        shard.entries.contains_key(&key)
+    }
+
+    /// Drops the entry under `key`, returns whether there was one
+    pub fn remove(&self, key: &K) -> bool {
+        self.shard(key).remove(key)
+    }
+
+    /// Drops expired entries, returns how many
+    pub fn sweep(&self) -> usize {
+        self.sweep_at(Instant::now())
+    }
+
+    fn sweep_at(&self, now: Instant) -> usize {
+        let mut swept = 0;
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            let expired: Vec<K> = shard
+                .entries
+                .iter()
+                .filter(|(_, slot)| slot.is_expired(now, self.inactive))
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in expired {
+                swept += shard.remove(&key) as usize;
+            }
+        }
+        swept
+    }
+
+    /// Drops every entry whose key doesn't pass `keep`
+    pub fn retain(&self, keep: impl Fn(&K) -> bool) {
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            let removed: Vec<K> = shard
+                .entries
+                .keys()
+                .filter(|key| !keep(key))
+                .cloned()
+                .collect();
+            for key in removed {
+                shard.remove(&key);
+            }
+        }
+    }
+
+    fn shard(&self, key: &K) -> MutexGuard<'_, Shard<K, V>> {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        // a panic mid update can't leave a shard inconsistent enough to matter for a cache
+        self.shards[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Debug)]
+    struct Sized(usize);
+
+    impl Weight for Sized {
+        fn weight(&self) -> usize {
+            self.0
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(u8, usize),
+        Get(u8),
+        Remove(u8),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..8u8, 0..48usize).prop_map(|(key, weight)| Op::Insert(key, weight)),
+            (0..8u8).prop_map(Op::Get),
+            (0..8u8).prop_map(Op::Remove),
+        ]
+    }
+
+    /// Plain LRU over a list, least recently used first
+    struct Model {
+        entries: Vec<(u8, usize)>,
+        capacity: usize,
+        max_entry_size: usize,
+    }
+
+    impl Model {
+        fn insert(&mut self, key: u8, weight: usize) -> bool {
+            // a max size of 0 turns the cache off
+            if self.max_entry_size == 0 || weight > self.max_entry_size || weight > self.capacity {
+                return false;
+            }
+            self.remove(key);
+            self.entries.push((key, weight));
+            while self.size() > self.capacity {
+                self.entries.remove(0);
+            }
+            true
+        }
+
+        fn get(&mut self, key: u8) -> Option<usize> {
+            let index = self.entries.iter().position(|(k, _)| *k == key)?;
+            let entry = self.entries.remove(index);
+            self.entries.push(entry);
+            Some(entry.1)
+        }
+
+        fn remove(&mut self, key: u8) {
+            self.entries.retain(|(k, _)| *k != key);
+        }
+
+        fn size(&self) -> usize {
+            self.entries.iter().map(|(_, weight)| weight).sum()
+        }
+    }
+
+    #[test]
+    fn test_expiry() {
+        let start = Instant::now();
+        let ttl = Duration::from_secs(60);
+        let cache: Cache<u8, Sized> =
+            Cache::new(1024, 1024, PolicyKind::Lru).with_expiry(Some(ttl), None);
+        cache.insert_at(1, Sized(1), start);
+        assert!(cache.get_at(&1, start + ttl / 2).is_some());
+        assert!(cache.get_at(&1, start + ttl).is_none());
+        assert_eq!(cache.shards[0].lock().unwrap().size, 0);
+
+        let inactive = Duration::from_secs(60);
+        let cache: Cache<u8, Sized> =
+            Cache::new(1024, 1024, PolicyKind::Lru).with_expiry(None, Some(inactive));
+        cache.insert_at(1, Sized(1), start);
+        cache.insert_at(2, Sized(1), start);
+        for step in 1..=3 {
+            assert!(cache.get_at(&1, start + inactive / 2 * step).is_some());
+        }
+        // 2 wasn't read, the sweeper drops it without a lookup
+        assert_eq!(cache.sweep_at(start + inactive / 2 * 3), 1);
+        assert!(cache.get_at(&1, start + inactive / 2 * 3).is_some());
+        assert_eq!(cache.sweep_interval(), Some(Duration::from_secs(30)));
+    }
+
+    proptest! {
+        #[test]
+        fn test_matches_model(
+            ops in prop::collection::vec(op(), 1..200),
+            capacity in 0..128usize,
+            max_entry_size in 0..64usize,
+        ) {
+            let cache: Cache<u8, Sized> =
+                Cache::with_shards(capacity, max_entry_size, 1, PolicyKind::Lru);
+            let mut model = Model { entries: Vec::new(), capacity, max_entry_size };
+            for op in ops {
+                match op {
+                    Op::Insert(key, weight) => {
+                        prop_assert_eq!(cache.insert(key, Sized(weight)), model.insert(key, weight));
+                    }
+                    Op::Get(key) => {
+                        prop_assert_eq!(cache.get(&key).map(|value| value.0), model.get(key));
+                    }
+                    Op::Remove(key) => {
+                        cache.retain(|k| *k != key);
+                        model.remove(key);
+                    }
+                }
+                let shard = cache.shards[0].lock().unwrap();
+                prop_assert_eq!(shard.size, model.size());
+                prop_assert!(shard.size <= capacity);
+                prop_assert_eq!(shard.entries.len(), model.entries.len());
+            }
+        }
+
+        #[test]
+        fn test_shards_stay_under_capacity(
+            inserts in prop::collection::vec((any::<u16>(), 0..256usize), 1..300),
+            capacity in 256..4096usize,
+        ) {
+            for policy in [PolicyKind::Lru, PolicyKind::Lfu, PolicyKind::TinyLfu, PolicyKind::Arc] {
+                let cache: Cache<u16, Sized> = Cache::new(capacity, 256, policy);
+                for &(key, weight) in &inserts {
+                    let inserted = cache.insert(key, Sized(weight));
+                    if inserted {
+                        prop_assert_eq!(cache.get(&key).map(|value| value.0), Some(weight));
+                    }
+                    for shard in cache.shards.iter() {
+                        let shard = shard.lock().unwrap();
+                        let total: usize = shard.entries.values().map(|slot| slot.weight).sum();
+                        prop_assert_eq!(shard.size, total);
+                        prop_assert!(shard.size <= shard.capacity);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::{
+    hash::Hash,
+    sync::{Arc, Weak},
+};
+
+use tokio::time::interval;
+
+use crate::cache::sharded::{Cache, Weight};
+
+/// Periodically drops expired entries nobody asks for anymore.
+/// Stops once the cache is dropped, does nothing when entries never expire.
+pub fn sweep_expired<K, V>(cache: &Arc<Cache<K, V>>)
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Weight + Send + Sync + 'static,
+{
+    let Some(period) = cache.sweep_interval() else {
+        return;
+    };
+    let cache: Weak<Cache<K, V>> = Arc::downgrade(cache);
+    tokio::spawn(async move {
+        let mut ticks = interval(period);
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            let Some(cache) = cache.upgrade() else {
+                return;
+            };
+            let swept = cache.sweep();
+            if swept > 0 {
+                println!("Swept {} expired cache entries", swept);
+            }
+        }
+    });
+}
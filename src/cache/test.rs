@@ -1,49 +1,55 @@
 #[cfg(test)]
 mod tests {
-    use crate::cache::lru::Cache;
+    use crate::{
+        cache::{
+            entry::{CacheEntry, CacheKey, FileCache},
+            policy::PolicyKind,
+            sharded::{Cache, Weight},
+        },
+        compression::encoder::Encoding,
+        http_parser::headers::Headers,
+    };
     use std::{path::Path, sync::Arc};
     use test_log::test;
 
+    fn entry(data: &[u8]) -> CacheEntry {
+        CacheEntry {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
     #[test(tokio::test)]
     async fn test_single_threaded_lru_cache() {
-        let cache = Cache::new(1024);
+        let cache = FileCache::new(1024 * 1024, 1024 * 1024, PolicyKind::Lru);
         let path = "/";
-        let key = Path::new(path).to_path_buf();
-        let data = b"Hello, world!".to_vec();
-        cache.add(&key, &data).await;
+        let key = CacheKey {
+            path: Path::new(path).to_path_buf(),
+            encoding: Encoding::None,
+        };
+        assert!(cache.insert(key.clone(), entry(b"Hello, world!")));
 
-        println!("Added to cache");
-
-        let result = cache.get(&key).await;
-
-        if result.is_some() {
-            println!("Getting from cache: {:?}", Some(result));
-        } else {
-            println!("Failed to get results");
-        }
+        let result = cache.get(&key).unwrap();
+        assert_eq!(&result.data[..], b"Hello, world!");
     }
 
     #[test(tokio::test)]
     async fn test_multithreaded_lru_cache() {
-        let cache = Arc::new(Cache::new(1024));
+        let cache = Arc::new(FileCache::new(1024 * 1024, 1024, PolicyKind::Lru));
         let paths = vec!["/", "/a", "/aa"];
         let mut handles = vec![];
         for path in paths {
             let cloned_cache = cache.clone();
 
             let handle = tokio::spawn(async move {
-                let key = Path::new(path).to_path_buf();
-                let data = b"Hello, world!".to_vec();
-                cloned_cache.add(&key, &data).await;
+                let key = CacheKey {
+                    path: Path::new(path).to_path_buf(),
+                    encoding: Encoding::None,
+                };
+                cloned_cache.insert(key.clone(), entry(b"Hello, world!"));
 
-                println!("Added {:?} to cloned_cache", key.clone());
-                let result = cloned_cache.get(&key).await;
-
-                if result.is_some() {
-                    println!("Getting from cache: {:?}", Some(result));
-                } else {
-                    println!("Failed to get results");
-                }
+                let result = cloned_cache.get(&key).unwrap();
+                assert_eq!(&result.data[..], b"Hello, world!");
             });
             handles.push(handle);
         }
@@ -52,4 +58,216 @@ mod tests {
             handle.await.unwrap();
         }
     }
+
+    #[test(tokio::test)]
+    async fn test_encodings_are_cached_separately() {
+        let cache = FileCache::new(1024 * 1024, 1024, PolicyKind::Lru);
+        let key = |encoding| CacheKey {
+            path: Path::new("/index.html").to_path_buf(),
+            encoding,
+        };
+        let mut headers = Headers::new();
+        headers.append("Content-Encoding", "gzip");
+        let gzip = CacheEntry {
+            headers,
+            ..entry(b"compressed")
+        };
+        cache.insert(key(Encoding::Gzip), gzip);
+        assert!(cache.get(&key(Encoding::None)).is_none());
+        assert!(cache.get(&key(Encoding::Brotli)).is_none());
+
+        let entry = cache.get(&key(Encoding::Gzip)).unwrap();
+        assert_eq!(&entry.data[..], b"compressed");
+        assert_eq!(entry.headers.get("content-encoding"), Some("gzip"));
+    }
+
+    #[test(tokio::test)]
+    async fn test_evict() {
+        let cache = FileCache::new(1024 * 1024, 1024, PolicyKind::Lru);
+        let key = |path: &str, encoding| CacheKey {
+            path: Path::new(path).to_path_buf(),
+            encoding,
+        };
+        let evict = |path: &str| cache.retain(|key| !key.path.starts_with(path));
+        cache.insert(key("/www/a.html", Encoding::None), entry(b"a"));
+        cache.insert(key("/www/a.html", Encoding::Gzip), entry(b"a"));
+        cache.insert(key("/www/docs/b.html", Encoding::None), entry(b"b"));
+        cache.insert(key("/www/docs/c.html", Encoding::None), entry(b"c"));
+
+        evict("/www/a.html");
+        assert!(cache.get(&key("/www/a.html", Encoding::None)).is_none());
+        assert!(cache.get(&key("/www/a.html", Encoding::Gzip)).is_none());
+        assert!(
+            cache
+                .get(&key("/www/docs/c.html", Encoding::None))
+                .is_some()
+        );
+
+        evict("/www/docs");
+        assert!(
+            cache
+                .get(&key("/www/docs/b.html", Encoding::None))
+                .is_none()
+        );
+        assert!(
+            cache
+                .get(&key("/www/docs/c.html", Encoding::None))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_entries_over_the_max_size_are_rejected() {
+        let cache = FileCache::new(1024, 16, PolicyKind::Lru);
+        let key = CacheKey::default();
+        assert!(!cache.insert(key.clone(), entry(&[0; 17])));
+        assert!(cache.get(&key).is_none());
+        assert!(cache.insert(key.clone(), entry(&[0; 16])));
+        assert!(cache.get(&key).is_some());
+
+        let disabled = FileCache::new(0, 0, PolicyKind::Lru);
+        assert!(!disabled.insert(key.clone(), entry(b"")));
+    }
+
+    struct Object(usize);
+
+    impl Weight for Object {
+        fn weight(&self) -> usize {
+            self.0
+        }
+    }
+
+    /// Many small assets with skewed popularity, a few large videos, and a crawler
+    /// walking through everything once now and then
+    fn synthetic_trace() -> Vec<(String, usize)> {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let assets = 5000;
+        // zipf(0.9) popularity
+        let mut cumulative = Vec::with_capacity(assets);
+        let mut total = 0.0;
+        for rank in 1..=assets {
+            total += 1.0 / (rank as f64).powf(0.9);
+            cumulative.push(total);
+        }
+
+        let mut trace = Vec::new();
+        for request in 0..200_000 {
+            if request % 50_000 == 25_000 {
+                for asset in 0..assets {
+                    trace.push((format!("/assets/{}", asset), 8 * 1024 + asset * 16));
+                }
+            }
+            if random() % 100 == 0 {
+                let video = random() as usize % 20;
+                trace.push((format!("/videos/{}.mp4", video), 40 * 1024 * 1024));
+                continue;
+            }
+            let point = (random() % 1_000_000) as f64 / 1_000_000.0 * total;
+            let asset = cumulative.partition_point(|&c| c < point);
+            trace.push((format!("/assets/{}", asset), 8 * 1024 + asset * 16));
+        }
+        trace
+    }
+
+    /// `<key> <bytes>` per line, lines that don't parse are skipped
+    fn parse_trace(trace: &str) -> Vec<(String, usize)> {
+        trace
+            .lines()
+            .filter_map(|line| {
+                let (key, size) = line.trim().rsplit_once(' ')?;
+                Some((key.to_string(), size.parse().ok()?))
+            })
+            .collect()
+    }
+
+    /// Hit and byte hit ratios of `policy` over `trace`, in percent
+    fn hit_ratios(trace: &[(String, usize)], capacity: usize, policy: PolicyKind) -> (f64, f64) {
+        let cache: Cache<String, Object> = Cache::new(capacity, capacity / 4, policy);
+        let (mut hits, mut hit_bytes) = (0, 0);
+        for (key, size) in trace {
+            if cache.get(key).is_some() {
+                hits += 1;
+                hit_bytes += size;
+            } else {
+                cache.insert(key.clone(), Object(*size));
+            }
+        }
+        let total_bytes: usize = trace.iter().map(|(_, size)| size).sum();
+        (
+            hits as f64 * 100.0 / trace.len() as f64,
+            hit_bytes as f64 * 100.0 / total_bytes as f64,
+        )
+    }
+
+    #[test]
+    fn test_recorded_trace() {
+        assert_eq!(
+            parse_trace("/a 10\n\n/b c 20\nbroken\n/c x\n"),
+            vec![("/a".to_string(), 10), ("/b c".to_string(), 20)]
+        );
+        // see test/README.md for the format and how to record a real one
+        let trace = parse_trace(include_str!("../../test/cache-trace.txt"));
+        assert_eq!(trace.len(), 78);
+        for policy in [
+            PolicyKind::Lru,
+            PolicyKind::Lfu,
+            PolicyKind::TinyLfu,
+            PolicyKind::Arc,
+        ] {
+            let (hits, byte_hits) = hit_ratios(&trace, 1024 * 1024, policy);
+            assert!(hits > 0.0 && byte_hits > 0.0, "{:?}", policy);
+        }
+    }
+
+    /// Hit ratios of every policy over `CACHE_TRACE`, or a synthetic trace without it:
+    /// `CACHE_TRACE=trace.txt cargo test bench_policy_hit_ratios -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_policy_hit_ratios() {
+        let trace = match std::env::var("CACHE_TRACE") {
+            Ok(path) => match std::fs::read_to_string(&path) {
+                Ok(trace) => parse_trace(&trace),
+                Err(e) => {
+                    println!("Can't read CACHE_TRACE {}: {}", path, e);
+                    return;
+                }
+            },
+            Err(_) => synthetic_trace(),
+        };
+        if trace.is_empty() {
+            println!("No `<key> <bytes>` lines in the trace");
+            return;
+        }
+        let capacity = std::env::var("CACHE_CAPACITY")
+            .ok()
+            .and_then(|capacity| capacity.parse().ok())
+            .unwrap_or(256 * 1024 * 1024);
+        println!(
+            "{} requests, {} MB cache",
+            trace.len(),
+            capacity / 1024 / 1024
+        );
+        println!("{:<10} {:>10} {:>10}", "policy", "hits", "byte hits");
+
+        for policy in [
+            PolicyKind::Lru,
+            PolicyKind::Lfu,
+            PolicyKind::TinyLfu,
+            PolicyKind::Arc,
+        ] {
+            let (hits, byte_hits) = hit_ratios(&trace, capacity, policy);
+            println!(
+                "{:<10} {:>9.2}% {:>9.2}%",
+                format!("{:?}", policy),
+                hits,
+                byte_hits
+            );
+        }
+    }
 }
@@ -0,0 +1,177 @@
+use std::hash::{BuildHasher, Hash, RandomState};
+
+use crate::cache::{lru::Lru, policy::Policy};
+
+/// Counters per row of the frequency sketch, a power of two
+const SKETCH_WIDTH: usize = 4096;
+
+/// W-TinyLFU (Einziger, Friedman and Manes): new keys land in a small LRU window, and
+/// leave it for the main LRU only if they were used more often than the key they'd displace.
+/// A run of one-off requests, like a crawler or a single big download, can't flush hot keys.
+pub struct TinyLfu<K> {
+    sketch: FrequencySketch,
+    window: Lru<K>,
+    main: Lru<K>,
+}
+
+impl<K: Hash + Eq + Clone> TinyLfu<K> {
+    pub fn new() -> TinyLfu<K> {
+        TinyLfu {
+            sketch: FrequencySketch::new(),
+            window: Lru::new(),
+            main: Lru::new(),
+        }
+    }
+
+    /// 1% of the cached keys, as in the paper
+    fn window_limit(&self) -> usize {
+        ((self.window.len() + self.main.len()) / 100).max(1)
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send> Policy<K> for TinyLfu<K> {
+    fn touch(&mut self, key: &K) {
+        self.sketch.increment(key);
+        if self.window.contains(key) {
+            self.window.touch(key);
+        } else if self.main.contains(key) {
+            self.main.touch(key);
+        }
+    }
+
+    fn miss(&mut self, key: &K) {
+        // misses count too, so a key that keeps coming back earns its place
+        self.sketch.increment(key);
+    }
+
+    fn insert(&mut self, key: &K) {
+        if self.window.contains(key) || self.main.contains(key) {
+            return self.touch(key);
+        }
+        self.window.touch(key);
+        // without an eviction in sight the window's oldest keys go to main unchallenged
+        while self.window.len() > self.window_limit() {
+            let Some(oldest) = self.window.pop() else {
+                break;
+            };
+            self.main.touch(&oldest);
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.window.remove(key);
+        self.main.remove(key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        let candidate = self.window.peek().cloned();
+        let victim = self.main.peek().cloned();
+        match (candidate, victim) {
+            (Some(candidate), Some(victim)) => {
+                self.window.remove(&candidate);
+                if self.sketch.frequency(&candidate) > self.sketch.frequency(&victim) {
+                    self.main.remove(&victim);
+                    self.main.touch(&candidate);
+                    Some(victim)
+                } else {
+                    Some(candidate)
+                }
+            }
+            (Some(candidate), None) => {
+                self.window.remove(&candidate);
+                Some(candidate)
+            }
+            (None, Some(victim)) => {
+                self.main.remove(&victim);
+                Some(victim)
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// Count-min sketch of 4 rows of 4 bit counters, halved periodically so old popularity fades
+struct FrequencySketch {
+    counters: Vec<u8>,
+    hasher: RandomState,
+    additions: usize,
+}
+
+impl FrequencySketch {
+    const ROWS: usize = 4;
+    const MAX: u8 = 15;
+    const SEEDS: [u64; 4] = [
+        0x9E37_79B9_7F4A_7C15,
+        0xC2B2_AE3D_27D4_EB4F,
+        0x1656_67B1_9E37_79F9,
+        0x85EB_CA77_C2B2_AE63,
+    ];
+
+    fn new() -> FrequencySketch {
+        FrequencySketch {
+            counters: vec![0; Self::ROWS * SKETCH_WIDTH],
+            hasher: RandomState::new(),
+            additions: 0,
+        }
+    }
+
+    fn slots<K: Hash>(&self, key: &K) -> [usize; 4] {
+        let hash = self.hasher.hash_one(key);
+        std::array::from_fn(|row| {
+            let mixed = hash.wrapping_mul(Self::SEEDS[row]) >> 32;
+            row * SKETCH_WIDTH + (mixed as usize & (SKETCH_WIDTH - 1))
+        })
+    }
+
+    fn frequency<K: Hash>(&self, key: &K) -> u8 {
+        self.slots(key)
+            .into_iter()
+            .map(|slot| self.counters[slot])
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn increment<K: Hash>(&mut self, key: &K) {
+        for slot in self.slots(key) {
+            self.counters[slot] = (self.counters[slot] + 1).min(Self::MAX);
+        }
+        self.additions += 1;
+        if self.additions >= 10 * SKETCH_WIDTH {
+            for counter in &mut self.counters {
+                *counter /= 2;
+            }
+            self.additions /= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_off_keys_are_not_admitted() {
+        let mut tinylfu = TinyLfu::new();
+        for key in 0..10 {
+            tinylfu.insert(&key);
+            for _ in 0..3 {
+                tinylfu.touch(&key);
+            }
+        }
+        // every new key in a scan loses against the hot keys and is evicted itself
+        for key in 100..200 {
+            tinylfu.miss(&key);
+            tinylfu.insert(&key);
+            assert_eq!(tinylfu.evict(), Some(key));
+        }
+        assert_eq!(tinylfu.main.len() + tinylfu.window.len(), 10);
+
+        // a key that keeps being asked for gets in
+        for _ in 0..5 {
+            tinylfu.miss(&500);
+        }
+        tinylfu.insert(&500);
+        assert_ne!(tinylfu.evict(), Some(500));
+        assert!(tinylfu.main.contains(&500));
+    }
+}
@@ -0,0 +1,86 @@
+use std::{path::PathBuf, sync::Arc};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher, recommended_watcher};
+use tokio::sync::mpsc::unbounded_channel;
+
+use crate::cache::entry::FileCache;
+
+/// Evicts cached files as soon as they change under one of `roots`.
+/// Watching stops once the returned watcher is dropped. Without one, for example when the
+/// system is out of inotify watches, entries are only compared with their file once they
+/// are older than `open_file_cache_valid`.
+pub fn watch_roots(cache: Arc<FileCache>, roots: &[PathBuf]) -> Option<RecommendedWatcher> {
+    let (tx, mut rx) = unbounded_channel();
+    let mut watcher = match recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = tx.send(event);
+    }) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Not watching files for changes: {}", e);
+            return None;
+        }
+    };
+
+    for root in roots {
+        // cache keys are canonical paths, events carry the watched path as a prefix
+        let watched = root
+            .canonicalize()
+            .map_err(notify::Error::io)
+            .and_then(|root| watcher.watch(&root, RecursiveMode::Recursive));
+        if let Err(e) = watched {
+            eprintln!("Not watching {:?} for changes: {}", root, e);
+        }
+    }
+
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            match event {
+                Ok(event) if !event.kind.is_access() => {
+                    // a directory event covers the files under it
+                    cache.retain(|key| !event.paths.iter().any(|path| key.path.starts_with(path)));
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("File watcher error: {}", e),
+            }
+        }
+    });
+
+    Some(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{
+        entry::{CacheEntry, CacheKey},
+        policy::PolicyKind,
+    };
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_watch_roots() {
+        let root = std::env::temp_dir().join(format!("rs-ngnix-watcher-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let file = root.join("index.html");
+        std::fs::write(&file, b"old").unwrap();
+
+        let cache = Arc::new(FileCache::new(1024, 1024, PolicyKind::Lru));
+        let missing = root.join("missing");
+        let _watcher = watch_roots(cache.clone(), &[missing, root.clone()]).unwrap();
+        let key = CacheKey {
+            path: file.canonicalize().unwrap(),
+            ..CacheKey::default()
+        };
+        cache.insert(key.clone(), CacheEntry::default());
+
+        std::fs::write(&file, b"new").unwrap();
+        let mut waited = Duration::ZERO;
+        while cache.get(&key).is_some() && waited < Duration::from_secs(5) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            waited += Duration::from_millis(10);
+        }
+        assert!(cache.get(&key).is_none());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
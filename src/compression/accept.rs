@@ -0,0 +1,151 @@
+use crate::http_parser::headers::Headers;
+
+/// Content codings a client accepts, from its `Accept-Encoding` fields (RFC 9110 12.5.3)
+#[derive(Debug, Default)]
+pub struct AcceptEncoding {
+    // lowercase coding or `*` with its weight in thousandths
+    codings: Vec<(String, u16)>,
+}
+
+impl AcceptEncoding {
+    pub fn new(headers: &Headers) -> AcceptEncoding {
+        let codings = headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("accept-encoding"))
+            .flat_map(|(_, value)| value.split(','))
+            .filter_map(parse_coding)
+            .collect();
+        AcceptEncoding { codings }
+    }
+
+    /// Weight given to a coding by name or through `*`, unlisted codings aren't acceptable
+    fn explicit(&self, coding: &str) -> Option<u16> {
+        let weight = |name: &str| {
+            self.codings
+                .iter()
+                .find(|(listed, _)| listed == name)
+                .map(|(_, weight)| *weight)
+        };
+        weight(&coding.to_ascii_lowercase()).or_else(|| weight("*"))
+    }
+
+    /// The uncompressed representation is acceptable unless refused by `identity;q=0` or `*;q=0`
+    pub fn identity_acceptable(&self) -> bool {
+        self.explicit("identity").unwrap_or(1000) > 0
+    }
+
+    /// Candidates the client accepts, best weight first and in the server's order between equals.
+    /// Codings weighted below an explicitly listed identity are left out.
+    pub fn ranked<T>(
+        &self,
+        candidates: impl IntoIterator<Item = T>,
+        name: impl Fn(&T) -> &str,
+    ) -> Vec<T> {
+        let identity = self.explicit("identity").unwrap_or(0);
+        let mut ranked: Vec<(u16, T)> = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let weight = self.explicit(name(&candidate)).unwrap_or(0);
+                (weight > 0 && weight >= identity).then_some((weight, candidate))
+            })
+            .collect();
+        // stable, so the server's preference breaks ties
+        ranked.sort_by(|(a, _), (b, _)| b.cmp(a));
+        ranked.into_iter().map(|(_, candidate)| candidate).collect()
+    }
+}
+
+/// `gzip;q=0.8` as (`gzip`, 800), elements with an invalid weight are ignored
+fn parse_coding(element: &str) -> Option<(String, u16)> {
+    let mut parts = element.split(';');
+    let coding = parts.next()?.trim().to_ascii_lowercase();
+    if coding.is_empty() {
+        return None;
+    }
+    let mut weight = 1000;
+    for param in parts {
+        let Some((name, value)) = param.split_once('=') else {
+            continue;
+        };
+        if name.trim().eq_ignore_ascii_case("q") {
+            weight = parse_qvalue(value.trim())?;
+        }
+    }
+    Some((coding, weight))
+}
+
+/// `qvalue = ( "0" [ "." 0*3DIGIT ] ) / ( "1" [ "." 0*3("0") ] )`
+fn parse_qvalue(value: &str) -> Option<u16> {
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let thousandths = format!("{:0<3}", fraction).parse::<u16>().ok()?;
+    match integer {
+        "0" => Some(thousandths),
+        "1" if thousandths == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> AcceptEncoding {
+        let mut headers = Headers::new();
+        headers.append("Accept-Encoding", value);
+        AcceptEncoding::new(&headers)
+    }
+
+    fn ranked<'a>(accept: &AcceptEncoding, candidates: &[&'a str]) -> Vec<&'a str> {
+        accept.ranked(candidates.iter().copied(), |name| name)
+    }
+
+    #[test]
+    fn test_ranked() {
+        let server = ["br", "zstd", "gzip"];
+        assert_eq!(
+            ranked(&accept("gzip, deflate, br, zstd"), &server),
+            ["br", "zstd", "gzip"]
+        );
+        assert_eq!(
+            ranked(&accept("gzip;q=1.0, br;q=0.5"), &server),
+            ["gzip", "br"]
+        );
+        assert_eq!(ranked(&accept("GZIP, br;q=0"), &server), ["gzip"]);
+        assert_eq!(
+            ranked(&accept("*;q=0.3, br;q=0"), &server),
+            ["zstd", "gzip"]
+        );
+        assert_eq!(
+            ranked(&accept("gzip;q=0.5, identity"), &server),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            ranked(&accept("gzip;q=2, br;q=0.1234"), &server),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            ranked(&AcceptEncoding::default(), &server),
+            Vec::<&str>::new()
+        );
+
+        let mut headers = Headers::new();
+        headers.append("Accept-Encoding", "gzip;q=0.5");
+        headers.append("accept-encoding", "zstd");
+        assert_eq!(
+            ranked(&AcceptEncoding::new(&headers), &server),
+            ["zstd", "gzip"]
+        );
+    }
+
+    #[test]
+    fn test_identity_acceptable() {
+        assert!(AcceptEncoding::default().identity_acceptable());
+        assert!(accept("gzip").identity_acceptable());
+        assert!(!accept("gzip, identity;q=0").identity_acceptable());
+        assert!(!accept("*;q=0").identity_acceptable());
+        assert!(accept("*;q=0, identity;q=0.1").identity_acceptable());
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+
+use async_compression::{
+    Level,
+    tokio::write::{BrotliEncoder, GzipEncoder, ZlibEncoder, ZstdEncoder},
+};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    compression::accept::AcceptEncoding,
+    config::ServerConfig,
+    constants::encodings::{BROTLI, DEFLATE, GZIP, ZSTD},
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+    Zstd,
+    Deflate,
+    #[default]
+    None,
+}
+
+impl Encoding {
+    /// Content coding named in `Accept-Encoding`, `None` for identity or unknown names
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        [
+            Encoding::Gzip,
+            Encoding::Brotli,
+            Encoding::Zstd,
+            Encoding::Deflate,
+        ]
+        .into_iter()
+        .find(|encoding| encoding.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => GZIP,
+            Encoding::Brotli => BROTLI,
+            Encoding::Zstd => ZSTD,
+            // zlib format, which is what HTTP calls deflate
+            Encoding::Deflate => DEFLATE,
+            Encoding::None => "identity",
+        }
+    }
+
+    /// Levels a server may configure, and the one used otherwise.
+    /// Brotli's own default of 11 is far too slow for compressing on the fly.
+    fn levels(&self) -> (i32, i32, i32) {
+        match self {
+            Encoding::Gzip | Encoding::Deflate => (1, 9, 6),
+            Encoding::Brotli => (0, 11, 5),
+            Encoding::Zstd => (1, 22, 3),
+            Encoding::None => (0, 0, 0),
+        }
+    }
+}
+
+/// Files below this size gain nothing from compression
+const DEFAULT_MIN_LENGTH: u64 = 20;
+
+/// Media types worth compressing, images, video and archives are compressed already
+const DEFAULT_TYPES: &[&str] = &[
+    "text/*",
+    "application/javascript",
+    "application/json",
+    "application/*+json",
+    "application/xml",
+    "application/*+xml",
+    "application/wasm",
+    "image/svg+xml",
+    "image/x-icon",
+    "image/x-ms-bmp",
+    "font/ttf",
+    "font/otf",
+];
+
+/// Encodings a server compresses responses with, in order of preference, and their levels
+#[derive(Debug)]
+pub struct Compression {
+    encodings: Vec<Encoding>,
+    levels: HashMap<Encoding, i32>,
+    min_length: u64,
+    types: Vec<String>,
+}
+
+impl Compression {
+    pub fn new(config: &ServerConfig) -> Result<Compression, io::Error> {
+        let encodings = match &config.compression {
+            Some(names) => names
+                .iter()
+                .map(|name| parse_encoding(name))
+                .collect::<Result<Vec<Encoding>, io::Error>>()?,
+            None => vec![Encoding::Gzip],
+        };
+
+        let mut levels = HashMap::new();
+        let gzip_comp_level = config
+            .gzip_comp_level
+            .map(|level| (GZIP.to_string(), level));
+        // the alias comes last so it overrides the map
+        for (name, level) in config
+            .compression_levels
+            .iter()
+            .flatten()
+            .map(|(name, level)| (name.clone(), *level))
+            .chain(gzip_comp_level)
+        {
+            let encoding = parse_encoding(&name)?;
+            let (min, max, _) = encoding.levels();
+            if !(min..=max).contains(&level) {
+                return Err(io::Error::other(format!(
+                    "Invalid {} level {}: expected {} to {}",
+                    name, level, min, max
+                )));
+            }
+            levels.insert(encoding, level);
+        }
+
+        let types = match &config.gzip_types {
+            Some(types) => types
+                .iter()
+                .map(|t| t.trim().to_ascii_lowercase())
+                .collect(),
+            None => DEFAULT_TYPES.iter().map(|t| t.to_string()).collect(),
+        };
+
+        Ok(Compression {
+            encodings,
+            levels,
+            min_length: config.gzip_min_length.unwrap_or(DEFAULT_MIN_LENGTH),
+            types,
+        })
+    }
+
+    /// Whether a file of this type and size is worth compressing
+    pub fn compresses(&self, content_type: &str, size: u64) -> bool {
+        size >= self.min_length && self.compresses_type(content_type)
+    }
+
+    pub fn compresses_type(&self, content_type: &str) -> bool {
+        let media_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        self.types
+            .iter()
+            .any(|pattern| type_matches(pattern, &media_type))
+    }
+
+    /// Best encoding the client accepts, `None` when the response should go uncompressed
+    pub fn negotiate(&self, accepted: &AcceptEncoding) -> Option<Encoding> {
+        accepted
+            .ranked(self.encodings.iter().copied(), |encoding| encoding.name())
+            .first()
+            .copied()
+    }
+
+    /// Whether responses depend on `Accept-Encoding`
+    pub fn is_enabled(&self) -> bool {
+        !self.encodings.is_empty()
+    }
+
+    pub fn level(&self, encoding: Encoding) -> i32 {
+        self.levels
+            .get(&encoding)
+            .copied()
+            .unwrap_or(encoding.levels().2)
+    }
+}
+
+/// `text/*` and `application/*+json` style patterns, with at most one `*`
+fn type_matches(pattern: &str, media_type: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            media_type.len() >= prefix.len() + suffix.len()
+                && media_type.starts_with(prefix)
+                && media_type.ends_with(suffix)
+        }
+        None => pattern == media_type,
+    }
+}
+
+fn parse_encoding(name: &str) -> Result<Encoding, io::Error> {
+    Encoding::from_name(name).ok_or_else(|| {
+        io::Error::other(format!(
+            "Invalid encoding {}: expected br, zstd, gzip or deflate",
+            name
+        ))
+    })
+}
+
+/// Compresses a whole body in memory, for responses that get cached
+pub async fn compress(data: &[u8], encoding: Encoding, level: i32) -> io::Result<Vec<u8>> {
+    let level = Level::Precise(level);
+    match encoding {
+        Encoding::Gzip => {
+            let encoder = GzipEncoder::with_quality(Vec::new(), level);
+            compress_all(data, encoder, GzipEncoder::into_inner).await
+        }
+        Encoding::Brotli => {
+            let encoder = BrotliEncoder::with_quality(Vec::new(), level);
+            compress_all(data, encoder, BrotliEncoder::into_inner).await
+        }
+        Encoding::Zstd => {
+            let encoder = ZstdEncoder::with_quality(Vec::new(), level);
+            compress_all(data, encoder, ZstdEncoder::into_inner).await
+        }
+        Encoding::Deflate => {
+            let encoder = ZlibEncoder::with_quality(Vec::new(), level);
+            compress_all(data, encoder, ZlibEncoder::into_inner).await
+        }
+        Encoding::None => Err(io::Error::other("Identity is not a compression")),
+    }
+}
+
+async fn compress_all<E: AsyncWrite + Unpin>(
+    data: &[u8],
+    mut encoder: E,
+    output: fn(E) -> Vec<u8>,
+) -> io::Result<Vec<u8>> {
+    encoder.write_all(data).await?;
+    encoder.shutdown().await?;
+    Ok(output(encoder))
+}
+
+/// Compresses `reader` as one continuous stream, written with chunked transfer coding
+pub async fn compress_stream<R, W>(
+    reader: R,
+    writer: W,
+    encoding: Encoding,
+    level: i32,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let level = Level::Precise(level);
+    match encoding {
+        Encoding::Gzip => {
+            let encoder = GzipEncoder::with_quality(Vec::new(), level);
+            compress_chunked(reader, writer, encoder, GzipEncoder::get_mut).await
+        }
+        Encoding::Brotli => {
+            let encoder = BrotliEncoder::with_quality(Vec::new(), level);
+            compress_chunked(reader, writer, encoder, BrotliEncoder::get_mut).await
+        }
+        Encoding::Zstd => {
+            let encoder = ZstdEncoder::with_quality(Vec::new(), level);
+            compress_chunked(reader, writer, encoder, ZstdEncoder::get_mut).await
+        }
+        Encoding::Deflate => {
+            let encoder = ZlibEncoder::with_quality(Vec::new(), level);
+            compress_chunked(reader, writer, encoder, ZlibEncoder::get_mut).await
+        }
+        Encoding::None => Err(io::Error::other("Identity is not a compression")),
+    }
+}
+
+/// Feeds the encoder and sends whatever output it has produced after each read as a chunk
+async fn compress_chunked<R, W, E>(
+    mut reader: R,
+    mut writer: W,
+    mut encoder: E,
+    output: fn(&mut E) -> &mut Vec<u8>,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    E: AsyncWrite + Unpin,
+{
+    let mut read_buf = [0u8; 32 * 1024];
+
+    loop {
+        let n = reader.read(&mut read_buf).await?;
+        if n == 0 {
+            break;
+        }
+        encoder.write_all(&read_buf[..n]).await?;
+        write_chunk(&mut writer, output(&mut encoder)).await?;
+    }
+    encoder.shutdown().await?;
+    write_chunk(&mut writer, output(&mut encoder)).await?;
+
+    // Final chunk (0-length)
+    writer.write_all(b"0\r\n\r\n").await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+async fn write_chunk<W: AsyncWrite + Unpin>(writer: &mut W, data: &mut Vec<u8>) -> io::Result<()> {
+    // an empty chunk would end the body
+    if data.is_empty() {
+        return Ok(());
+    }
+    writer
+        .write_all(format!("{:X}\r\n", data.len()).as_bytes())
+        .await?;
+    writer.write_all(data).await?;
+    writer.write_all(b"\r\n").await?;
+    data.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_parser::{body::Body, headers::Headers, reader::HttpReader};
+    use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder, ZstdDecoder};
+
+    async fn round_trip(encoding: Encoding, input: &[u8]) -> Vec<u8> {
+        let mut chunked = Vec::new();
+        compress_stream(input, &mut chunked, encoding, encoding.levels().2)
+            .await
+            .unwrap();
+
+        let mut compressed = Vec::new();
+        HttpReader::new(1024)
+            .copy_body(
+                &mut chunked.as_slice(),
+                &mut compressed,
+                Body::Chunked,
+                true,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            compressed,
+            compress(input, encoding, encoding.levels().2)
+                .await
+                .unwrap(),
+            "{:?}",
+            encoding
+        );
+
+        let mut output = Vec::new();
+        let compressed = compressed.as_slice();
+        match encoding {
+            Encoding::Gzip => GzipDecoder::new(compressed).read_to_end(&mut output).await,
+            Encoding::Brotli => {
+                BrotliDecoder::new(compressed)
+                    .read_to_end(&mut output)
+                    .await
+            }
+            Encoding::Zstd => ZstdDecoder::new(compressed).read_to_end(&mut output).await,
+            Encoding::Deflate => ZlibDecoder::new(compressed).read_to_end(&mut output).await,
+            Encoding::None => unreachable!(),
+        }
+        .unwrap();
+        output
+    }
+
+    #[tokio::test]
+    async fn test_compress_stream() {
+        let input: Vec<u8> = (0..200_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        for encoding in [
+            Encoding::Gzip,
+            Encoding::Brotli,
+            Encoding::Zstd,
+            Encoding::Deflate,
+        ] {
+            assert_eq!(round_trip(encoding, &input).await, input, "{:?}", encoding);
+        }
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let config: ServerConfig = serde_yaml::from_str(
+            "listen: 80\ncompression: [br, zstd, gzip]\ncompression_levels:\n  br: 4\n  gzip: 9",
+        )
+        .unwrap();
+        let compression = Compression::new(&config).unwrap();
+        let accept = |value: &str| {
+            let mut headers = Headers::new();
+            headers.append("Accept-Encoding", value);
+            compression.negotiate(&AcceptEncoding::new(&headers))
+        };
+        assert_eq!(accept("gzip, br"), Some(Encoding::Brotli));
+        assert_eq!(accept("GZIP"), Some(Encoding::Gzip));
+        assert_eq!(accept("br;q=0.5, gzip"), Some(Encoding::Gzip));
+        assert_eq!(accept("deflate"), None);
+        assert_eq!(compression.level(Encoding::Brotli), 4);
+        assert_eq!(compression.level(Encoding::Zstd), 3);
+
+        assert!(compression.compresses("text/html; charset=utf-8", 20));
+        assert!(compression.compresses("application/manifest+json", 1000));
+        assert!(!compression.compresses("text/html", 19));
+        assert!(!compression.compresses("image/png", 1000));
+
+        let config: ServerConfig = serde_yaml::from_str(
+            "listen: 80\ncompression_levels:\n  gzip: 9\ngzip_comp_level: 1\ngzip_min_length: 1024\ngzip_types: [text/html, '*+xml']",
+        )
+        .unwrap();
+        let compression = Compression::new(&config).unwrap();
+        assert_eq!(compression.level(Encoding::Gzip), 1);
+        assert!(compression.compresses("TEXT/HTML", 1024));
+        assert!(compression.compresses("application/rss+xml", 1024));
+        assert!(!compression.compresses("text/css", 1024));
+        assert!(!compression.compresses("text/html", 1023));
+
+        let invalid: ServerConfig =
+            serde_yaml::from_str("listen: 80\ngzip_comp_level: 10").unwrap();
+        assert!(Compression::new(&invalid).is_err());
+        let invalid: ServerConfig =
+            serde_yaml::from_str("listen: 80\ncompression_levels:\n  br: 12").unwrap();
+        assert!(Compression::new(&invalid).is_err());
+        let unknown: ServerConfig =
+            serde_yaml::from_str("listen: 80\ncompression: [lzma]").unwrap();
+        assert!(Compression::new(&unknown).is_err());
+    }
+}
@@ -1 +1,2 @@
-pub mod gzip;
+pub mod accept;
+pub mod encoder;
@@ -1,15 +1,26 @@
-use std::{fs::File, io::Error, path::Path, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Error,
+    path::Path,
+};
 
 use serde::{Deserialize, Serialize};
 use tokio::task::JoinHandle;
 
-use crate::listener::http::listen;
-
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-pub struct StaticFileConfig {
-    pub path: String,
-    pub dir: Vec<String>,
-}
+use crate::{
+    cache::{policy::PolicyKind, proxy::ProxyCache},
+    compression::encoder::Compression,
+    handler::{autoindex::AutoIndex, error_page::ErrorPages, forwarded::Forwarded},
+    listener::{http::listen, tls::tls_acceptor},
+    response_builder::mime::MimeTypes,
+    router::{
+        location::parse_return,
+        matcher::Matcher,
+        server_name::ServerName,
+        try_files::{Fallback, TryFiles},
+    },
+};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(untagged)]
@@ -18,15 +29,103 @@ pub enum ProxyType {
     Multiple(Vec<String>),
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct ServerConfig {
-    pub listen: u16,
-    pub cache: Option<usize>,
-    pub root: Option<String>,
+/// Backends of a proxy, shared by servers and locations
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct UpstreamConfig {
     pub proxy: Option<ProxyType>,
     pub proxy_health: Option<String>,
     pub strategy: Option<String>,
     pub weights: Option<Vec<u8>>,
+    pub upstream_keepalive: Option<usize>, // max idle connections per backend
+    pub upstream_keepalive_timeout: Option<u64>, // in s
+    pub proxy_cache: Option<usize>,        // in KB, cacheable GET responses are kept when set
+    pub proxy_cache_key: Option<String>, // `$scheme$host$request_uri` by default, also `$uri`, `$args`, `$http_<name>`
+    pub proxy_cache_inactive: Option<u64>, // in s, responses not read within this window are dropped
+    pub proxy_cache_path: Option<String>, // directory responses are also kept in, across reloads and restarts
+    pub proxy_cache_max_size: Option<u64>, // in MB, of `proxy_cache_path`, 1024 by default
+}
+
+/// Request handling for the URIs matching `path`, which is one of
+/// `= /exact`, `^~ /prefix`, `~ regex`, `~* case-insensitive-regex`, `/prefix`
+/// or `@name` for locations only reached through `try_files`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct LocationConfig {
+    pub path: String,
+    pub root: Option<String>,
+    #[serde(flatten)]
+    pub upstream: UpstreamConfig,
+    #[serde(rename = "return")]
+    pub return_response: Option<String>, // `301 https://example.com$request_uri` or `403 text`
+    pub try_files: Option<Vec<String>>, // `$uri`, `$uri/`, then `=404`, `/index.html` or `@name`
+}
+
+/// Client information headers added to proxied requests
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default)]
+pub struct ForwardedConfig {
+    pub x_forwarded_for: bool,
+    pub x_forwarded_proto: bool,
+    pub x_forwarded_host: bool,
+    pub x_real_ip: bool,
+    pub forwarded: bool,
+    pub trusted_proxies: Vec<String>, // IPs or CIDR ranges whose inbound values are kept
+    pub strip_untrusted: bool,
+}
+
+impl Default for ForwardedConfig {
+    fn default() -> Self {
+        ForwardedConfig {
+            x_forwarded_for: true,
+            x_forwarded_proto: true,
+            x_forwarded_host: true,
+            x_real_ip: true,
+            forwarded: true,
+            trusted_proxies: Vec::new(),
+            strip_untrusted: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ServerConfig {
+    pub listen: u16,
+    pub server_name: Option<Vec<String>>, // `example.com`, `*.example.com`, `www.example.*` or `~regex`
+    pub default_server: Option<bool>,
+    pub cache: Option<usize>,                // in KB
+    pub cache_max_entry_size: Option<usize>, // in KB, larger files aren't cached, 1/8 of `cache` by default
+    pub cache_policy: Option<String>,        // `lru`, `lfu`, `w-tinylfu` or `arc`
+    pub open_file_cache_valid: Option<u64>, // in s, how long cached files are trusted before an mtime check
+    pub cache_ttl: Option<u64>, // in s, entries are dropped this long after being cached
+    pub cache_inactive: Option<u64>, // in s, entries not read within this window are dropped
+    pub root: Option<String>,
+    #[serde(flatten)]
+    pub upstream: UpstreamConfig,
+    pub locations: Option<Vec<LocationConfig>>,
+    pub max_header_size: Option<usize>, // in KB
+    pub keepalive_timeout: Option<u64>, // in s
+    pub keepalive_requests: Option<usize>,
+    pub forwarded_headers: Option<ForwardedConfig>,
+    pub ssl_certificate: Option<String>, // PEM, leaf certificate first
+    pub ssl_certificate_key: Option<String>, // PEM
+    pub ssl_protocols: Option<Vec<String>>, // `TLSv1.2`, `TLSv1.3`
+    pub ssl_ciphers: Option<Vec<String>>, // rustls names like `TLS13_AES_256_GCM_SHA384`
+    pub types: Option<HashMap<String, String>>, // extension to MIME type, `wasm: application/wasm`
+    pub default_type: Option<String>,
+    pub nosniff: Option<bool>, // sends `X-Content-Type-Options: nosniff`
+    pub index: Option<Vec<String>>, // files tried for directory requests, in order
+    pub autoindex: Option<bool>,
+    pub autoindex_format: Option<String>, // `html` or `json`
+    pub autoindex_show_hidden: Option<bool>,
+    pub try_files: Option<Vec<String>>, // for the server root, locations set their own
+    pub error_page: Option<HashMap<u16, String>>, // `404: /404.html` or `503: https://status.example.com`
+    pub gzip_static: Option<bool>,                // serve `file.gz` when the client accepts gzip
+    pub brotli_static: Option<bool>,              // serve `file.br`
+    pub zstd_static: Option<bool>,                // serve `file.zst`
+    pub compression: Option<Vec<String>>, // encodings compressed on the fly, in order of preference
+    pub compression_levels: Option<HashMap<String, i32>>, // `br: 5`, `zstd: 3`, `gzip: 6`
+    pub gzip_comp_level: Option<i32>, // same as `compression_levels: {gzip: ..}`, and wins over it
+    pub gzip_min_length: Option<u64>, // smaller files are sent uncompressed, in every encoding
+    pub gzip_types: Option<Vec<String>>, // `text/*`, `application/*+json` or `*`, for every encoding
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -46,28 +145,152 @@ pub fn read_config(config_path: &Path) -> Option<Config> {
 }
 
 fn validate(config: &Config) -> Result<(), Error> {
+    for (port, servers) in group_by_port(config) {
+        let default_servers = servers
+            .iter()
+            .filter(|server| server.default_server.unwrap_or(false))
+            .count();
+        if default_servers > 1 {
+            return Err(Error::other(format!(
+                "Invalid Config: port {} has more than one default_server",
+                port
+            )));
+        }
+        tls_acceptor(&servers)?;
+    }
+
     for server_config in &config.http {
-        if let Some(weights) = &server_config.weights
-            && let Some(proxy) = &server_config.proxy
-        {
-            match proxy {
-                ProxyType::Multiple(p) => {
-                    if weights.len() != p.len() {
-                        return Err(Error::other(
-                            "Invalid weights: Weight length is not equal to proxy lenght",
-                        ));
-                    }
-                    for weight in weights {
-                        if *weight == 0 {
-                            return Err(Error::other("Invalid weights: Weights can't be 0"));
-                        }
-                    }
+        for name in server_config.server_name.iter().flatten() {
+            ServerName::parse(name)?;
+        }
+        if let Some(forwarded_config) = &server_config.forwarded_headers {
+            Forwarded::new(forwarded_config)?;
+        }
+        validate_upstream(&server_config.upstream)?;
+        MimeTypes::new(server_config)?;
+        if let Some(try_files) = &server_config.try_files {
+            validate_try_files(server_config, try_files)?;
+        }
+        AutoIndex::new(server_config)?;
+        ErrorPages::new(server_config)?;
+        Compression::new(server_config)?;
+        if let Some(policy) = &server_config.cache_policy {
+            PolicyKind::parse(policy)?;
+        }
+        for (name, value) in [
+            ("cache_ttl", server_config.cache_ttl),
+            ("cache_inactive", server_config.cache_inactive),
+        ] {
+            if value == Some(0) {
+                return Err(Error::other(format!(
+                    "Invalid {}: expected at least 1 second",
+                    name
+                )));
+            }
+        }
+        for index in server_config.index.iter().flatten() {
+            if index.is_empty() || index.contains(['/', '\\']) {
+                return Err(Error::other(format!(
+                    "Invalid index {}: expected a file name",
+                    index
+                )));
+            }
+        }
+
+        for location in server_config.locations.iter().flatten() {
+            Matcher::parse(&location.path)?;
+            let handlers = [
+                location.root.is_some(),
+                location.upstream.proxy.is_some(),
+                location.return_response.is_some(),
+            ];
+            if handlers.iter().filter(|set| **set).count() != 1 {
+                return Err(Error::other(format!(
+                    "Invalid location {}: exactly one of root, proxy or return is needed",
+                    location.path
+                )));
+            }
+            if let Some(return_response) = &location.return_response {
+                parse_return(return_response)?;
+            }
+            if let Some(try_files) = &location.try_files {
+                if location.root.is_none() {
+                    return Err(Error::other(format!(
+                        "Invalid location {}: try_files needs a root",
+                        location.path
+                    )));
                 }
-                ProxyType::Single(_) => {
+                validate_try_files(server_config, try_files)?;
+            }
+            validate_upstream(&location.upstream)?;
+        }
+    }
+
+    // every proxy keeps its own size accounting of its directory
+    let mut cache_paths = HashSet::new();
+    let proxies = config.http.iter().flat_map(|server_config| {
+        let fallback = server_config
+            .root
+            .is_none()
+            .then_some(&server_config.upstream);
+        let locations = server_config.locations.iter().flatten();
+        fallback.into_iter().chain(
+            locations
+                .filter(|location| location.upstream.proxy.is_some())
+                .map(|location| &location.upstream),
+        )
+    });
+    for path in proxies.filter_map(|upstream| upstream.proxy_cache_path.as_deref()) {
+        if !cache_paths.insert(Path::new(path)) {
+            return Err(Error::other(format!(
+                "Invalid proxy_cache_path {}: used by more than one proxy",
+                path
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Named fallbacks have to exist in the same server
+fn validate_try_files(server_config: &ServerConfig, try_files: &[String]) -> Result<(), Error> {
+    if let Fallback::Named(name) = TryFiles::parse(try_files)?.fallback
+        && !server_config
+            .locations
+            .iter()
+            .flatten()
+            .any(|location| location.path.trim() == name)
+    {
+        return Err(Error::other(format!(
+            "Invalid try_files: location {} doesn't exist",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn validate_upstream(upstream: &UpstreamConfig) -> Result<(), Error> {
+    ProxyCache::validate(upstream)?;
+    if let Some(weights) = &upstream.weights
+        && let Some(proxy) = &upstream.proxy
+    {
+        match proxy {
+            ProxyType::Multiple(p) => {
+                if weights.len() != p.len() {
                     return Err(Error::other(
-                        "Invalid Config: weights property is only for multiply proxy addresses",
+                        "Invalid weights: Weight length is not equal to proxy lenght",
                     ));
                 }
+                for weight in weights {
+                    if *weight == 0 {
+                        return Err(Error::other("Invalid weights: Weights can't be 0"));
+                    }
+                }
+            }
+            ProxyType::Single(_) => {
+                return Err(Error::other(
+                    "Invalid Config: weights property is only for multiply proxy addresses",
+                ));
             }
         }
     }
@@ -75,19 +298,30 @@ fn validate(config: &Config) -> Result<(), Error> {
     Ok(())
 }
 
+/// Server blocks per port, in the order the ports first appear
+fn group_by_port(config: &Config) -> Vec<(u16, Vec<ServerConfig>)> {
+    let mut ports: Vec<(u16, Vec<ServerConfig>)> = Vec::new();
+    for server in &config.http {
+        match ports.iter_mut().find(|(port, _)| *port == server.listen) {
+            Some((_, servers)) => servers.push(server.clone()),
+            None => ports.push((server.listen, vec![server.clone()])),
+        }
+    }
+    ports
+}
+
 pub fn execute_config(config_path: &Path) -> Vec<JoinHandle<()>> {
     let config = read_config(config_path);
     if config.is_none() {
         return Vec::new();
     }
 
-    let config = Arc::new(config.unwrap());
+    let config = config.unwrap();
     let mut handles: Vec<JoinHandle<()>> = Vec::new();
-    for server in &config.http {
-        let server = server.clone(); // Clone before moving into async block
+    for (port, servers) in group_by_port(&config) {
         let handle = tokio::spawn(async move {
-            if let Err(e) = listen(&server).await {
-                eprintln!("Error on port {}: {}", server.listen, e);
+            if let Err(e) = listen(port, &servers).await {
+                eprintln!("Error on port {}: {}", port, e);
             }
         });
 
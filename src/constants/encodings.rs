@@ -1 +1,4 @@
 pub const GZIP: &str = "gzip";
+pub const BROTLI: &str = "br";
+pub const ZSTD: &str = "zstd";
+pub const DEFLATE: &str = "deflate";
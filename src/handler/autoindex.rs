@@ -0,0 +1,305 @@
+use std::{
+    cmp::Ordering,
+    io::Error,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::fs;
+
+use crate::{
+    config::ServerConfig,
+    handler::connection::ClientStream,
+    http_parser::request::Request,
+    response_builder::{response::Response, status::Status},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListingFormat {
+    Html,
+    Json,
+}
+
+/// Directory listings for directories without an index file
+#[derive(Debug)]
+pub struct AutoIndex {
+    format: ListingFormat,
+    show_hidden: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl AutoIndex {
+    pub fn new(config: &ServerConfig) -> Result<Option<AutoIndex>, Error> {
+        let format = match config.autoindex_format.as_deref() {
+            None | Some("html") => ListingFormat::Html,
+            Some("json") => ListingFormat::Json,
+            Some(format) => {
+                return Err(Error::other(format!(
+                    "Invalid autoindex_format {}: expected html or json",
+                    format
+                )));
+            }
+        };
+        if !config.autoindex.unwrap_or(false) {
+            return Ok(None);
+        }
+
+        Ok(Some(AutoIndex {
+            format,
+            show_hidden: config.autoindex_show_hidden.unwrap_or(false),
+        }))
+    }
+
+    /// Lists `directory`, sorted by the `sort=name|size|mtime` and `order=asc|desc` query parameters
+    pub async fn send<S: ClientStream>(
+        &self,
+        stream: &mut S,
+        request: &Request,
+        directory: &Path,
+        keep_alive: bool,
+    ) -> Result<(), Error> {
+        let mut entries = self.read_entries(directory).await?;
+        let (key, descending) = sort_order(request.query().unwrap_or(""));
+        sort_entries(&mut entries, key, descending);
+
+        let (body, content_type) = match self.format {
+            ListingFormat::Html => (
+                render_html(&request.uri, &entries),
+                "text/html; charset=utf-8",
+            ),
+            ListingFormat::Json => (render_json(&entries), "application/json; charset=utf-8"),
+        };
+        let head_only = request.method.eq_ignore_ascii_case("head");
+        Response::new(Status::Ok)
+            .body(content_type, body)
+            .keep_alive(keep_alive)
+            .send(stream, head_only)
+            .await
+    }
+
+    async fn read_entries(&self, directory: &Path) -> Result<Vec<Entry>, Error> {
+        let mut entries = Vec::new();
+        let mut dir = fs::read_dir(directory).await?;
+        while let Some(dir_entry) = dir.next_entry().await? {
+            let Ok(name) = dir_entry.file_name().into_string() else {
+                continue;
+            };
+            if name.starts_with('.') && !self.show_hidden {
+                continue;
+            }
+            // follows symlinks, broken ones are left out
+            let Ok(metadata) = fs::metadata(dir_entry.path()).await else {
+                continue;
+            };
+            entries.push(Entry {
+                name,
+                is_dir: metadata.is_dir(),
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+        Ok(entries)
+    }
+}
+
+fn sort_order(query: &str) -> (SortKey, bool) {
+    let mut key = SortKey::Name;
+    let mut descending = false;
+    for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        match (name, value) {
+            ("sort", "name") => key = SortKey::Name,
+            ("sort", "size") => key = SortKey::Size,
+            ("sort", "mtime") => key = SortKey::Modified,
+            ("order", "asc") => descending = false,
+            ("order", "desc") => descending = true,
+            _ => {}
+        }
+    }
+    (key, descending)
+}
+
+/// Directories always come first, ties are broken by name
+fn sort_entries(entries: &mut [Entry], key: SortKey, descending: bool) {
+    entries.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        }
+        .then_with(|| a.name.cmp(&b.name));
+        let ordering = if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        b.is_dir.cmp(&a.is_dir).then(ordering)
+    });
+}
+
+fn render_html(uri: &str, entries: &[Entry]) -> String {
+    let title = format!("Index of {}", escape_html(uri));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{}</title></head>\n<body>\n<h1>{}</h1>\n<table>\n<tr><th><a href=\"?sort=name\">Name</a></th><th><a href=\"?sort=mtime\">Last modified</a></th><th><a href=\"?sort=size\">Size</a></th></tr>\n<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n",
+        title, title
+    );
+    for entry in entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        let size = if entry.is_dir {
+            "-".to_string()
+        } else {
+            entry.size.to_string()
+        };
+        let modified = entry
+            .modified
+            .map(httpdate::fmt_http_date)
+            .unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            encode_href(&entry.name),
+            suffix,
+            escape_html(&entry.name),
+            suffix,
+            modified,
+            size
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Same shape as nginx's `autoindex_format json`, with `mtime` as unix seconds
+fn render_json(entries: &[Entry]) -> String {
+    let items: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let mtime = entry
+                .modified
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0);
+            if entry.is_dir {
+                format!(
+                    "{{\"name\":\"{}\",\"type\":\"directory\",\"mtime\":{}}}",
+                    escape_json(&entry.name),
+                    mtime
+                )
+            } else {
+                format!(
+                    "{{\"name\":\"{}\",\"type\":\"file\",\"mtime\":{},\"size\":{}}}",
+                    escape_json(&entry.name),
+                    mtime,
+                    entry.size
+                )
+            }
+        })
+        .collect();
+    format!("[{}]", items.join(","))
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Percent-encodes everything but unreserved characters so names are safe inside `href`
+fn encode_href(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn entry(name: &str, is_dir: bool, size: u64, modified: u64) -> Entry {
+        Entry {
+            name: name.to_string(),
+            is_dir,
+            size,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(modified)),
+        }
+    }
+
+    fn names(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_sort_entries() {
+        let mut entries = vec![
+            entry("b.txt", false, 10, 3),
+            entry("z", true, 4096, 1),
+            entry("a.txt", false, 30, 2),
+            entry("c.txt", false, 20, 1),
+        ];
+        let (key, descending) = sort_order("");
+        sort_entries(&mut entries, key, descending);
+        assert_eq!(names(&entries), ["z", "a.txt", "b.txt", "c.txt"]);
+
+        let (key, descending) = sort_order("sort=size&order=desc");
+        sort_entries(&mut entries, key, descending);
+        assert_eq!(names(&entries), ["z", "a.txt", "c.txt", "b.txt"]);
+
+        let (key, descending) = sort_order("sort=mtime");
+        sort_entries(&mut entries, key, descending);
+        assert_eq!(names(&entries), ["z", "c.txt", "a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn test_render() {
+        let entries = vec![
+            entry("docs", true, 4096, 60),
+            entry("a \"<b>\".txt", false, 5, 0),
+        ];
+
+        let html = render_html("/files/", &entries);
+        assert!(html.contains("<title>Index of /files/</title>"));
+        assert!(html.contains("<a href=\"docs/\">docs/</a>"));
+        assert!(html.contains(
+            "<a href=\"a%20%22%3Cb%3E%22.txt\">a &quot;&lt;b&gt;&quot;.txt</a></td><td>Thu, 01 Jan 1970 00:00:00 GMT</td><td>5</td>"
+        ));
+
+        assert_eq!(
+            render_json(&entries),
+            "[{\"name\":\"docs\",\"type\":\"directory\",\"mtime\":60},{\"name\":\"a \\\"<b>\\\".txt\",\"type\":\"file\",\"mtime\":0,\"size\":5}]"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    fs::Metadata,
+    io::Error,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    handler::connection::ClientStream,
+    http_parser::{headers::Headers, request::Request},
+    response_builder::{response::Response, status::Status},
+};
+
+/// `ETag` and `Last-Modified` of a file, derived like nginx from its mtime and size
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Validators {
+    size: u64,
+    modified: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Precondition {
+    Proceed,
+    NotModified,
+    Failed,
+}
+
+impl Validators {
+    pub fn new(metadata: &Metadata) -> Validators {
+        Validators {
+            size: metadata.len(),
+            modified: metadata.modified().ok().and_then(unix_seconds),
+        }
+    }
+
+    /// Strong tag of the identity representation
+    pub fn etag(&self) -> String {
+        format!("\"{:x}-{:x}\"", self.modified.unwrap_or(0), self.size)
+    }
+
+    /// Headers for a response, encoded representations get a weak tag
+    /// since their bytes differ from the file on disk
+    pub fn headers(&self, weak: bool) -> Headers {
+        let mut headers = Headers::new();
+        let prefix = if weak { "W/" } else { "" };
+        headers.append("ETag", &format!("{}{}", prefix, self.etag()));
+        if let Some(modified) = self.modified {
+            headers.append(
+                "Last-Modified",
+                &httpdate::fmt_http_date(UNIX_EPOCH + std::time::Duration::from_secs(modified)),
+            );
+        }
+        headers
+    }
+
+    /// `If-Range` needs a strong tag match or the exact modification date (RFC 9110 section 13.1.5)
+    pub fn matches_if_range(&self, if_range: &str) -> bool {
+        let if_range = if_range.trim();
+        if if_range.starts_with('"') {
+            return if_range == self.etag();
+        }
+        match (parse_date(if_range), self.modified) {
+            (Some(date), Some(modified)) => date == modified,
+            _ => false,
+        }
+    }
+}
+
+/// Evaluates the conditional request headers in the order of RFC 9110 section 13.2.2
+pub fn evaluate(request: &Request, validators: &Validators) -> Precondition {
+    evaluate_validators(request, Some(&validators.etag()), validators.modified)
+}
+
+/// Like `evaluate`, against the `ETag` and `Last-Modified` of stored response headers
+pub fn evaluate_response(request: &Request, headers: &Headers) -> Precondition {
+    let modified = headers.get("last-modified").and_then(parse_date);
+    evaluate_validators(request, headers.get("etag").map(str::trim), modified)
+}
+
+fn evaluate_validators(
+    request: &Request,
+    etag: Option<&str>,
+    modified: Option<u64>,
+) -> Precondition {
+    let headers = &request.headers;
+    let get_or_head =
+        request.method.eq_ignore_ascii_case("get") || request.method.eq_ignore_ascii_case("head");
+
+    if let Some(if_match) = headers.get("if-match") {
+        if !matches_any(if_match, etag, false) {
+            return Precondition::Failed;
+        }
+    } else if let Some(date) = headers.get("if-unmodified-since").and_then(parse_date)
+        && modified.is_some_and(|modified| modified > date)
+    {
+        return Precondition::Failed;
+    }
+
+    if let Some(if_none_match) = headers.get("if-none-match") {
+        if matches_any(if_none_match, etag, true) {
+            return match get_or_head {
+                true => Precondition::NotModified,
+                false => Precondition::Failed,
+            };
+        }
+    } else if get_or_head
+        && let Some(date) = headers.get("if-modified-since").and_then(parse_date)
+        && modified.is_some_and(|modified| modified <= date)
+    {
+        return Precondition::NotModified;
+    }
+
+    Precondition::Proceed
+}
+
+/// Whether `list` has `etag`, the weak comparison ignores `W/` on both sides (RFC 9110 section 8.8.3.2)
+fn matches_any(list: &str, etag: Option<&str>, weak: bool) -> bool {
+    list.split(',').map(str::trim).any(|tag| {
+        if tag == "*" {
+            return true;
+        }
+        let Some(etag) = etag else {
+            return false;
+        };
+        match (tag.strip_prefix("W/"), etag.strip_prefix("W/")) {
+            (None, None) => tag == etag,
+            (listed, stored) => weak && listed.unwrap_or(tag) == stored.unwrap_or(etag),
+        }
+    })
+}
+
+/// Answers a failed or satisfied precondition, returns false when the request should proceed.
+/// A 304 carries `headers`, the validators, `Vary` and caching headers the full response would have.
+pub async fn send_precondition<S: ClientStream>(
+    stream: &mut S,
+    precondition: Precondition,
+    headers: &Headers,
+    keep_alive: bool,
+) -> Result<bool, Error> {
+    let response = match precondition {
+        Precondition::Proceed => return Ok(false),
+        Precondition::NotModified => Response::new(Status::NotModified).headers(headers),
+        Precondition::Failed => Response::new(Status::PreconditionFailed).empty(),
+    };
+    response.keep_alive(keep_alive).send(stream, true).await?;
+    Ok(true)
+}
+
+fn parse_date(value: &str) -> Option<u64> {
+    httpdate::parse_http_date(value.trim())
+        .ok()
+        .and_then(unix_seconds)
+}
+
+fn unix_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_parser::{request::parse_request, response::parse_response};
+
+    const MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+    fn validators() -> Validators {
+        Validators {
+            size: 1024,
+            modified: Some(784111777),
+        }
+    }
+
+    fn evaluate_headers(method: &str, headers: &str) -> Precondition {
+        let head = format!("{} / HTTP/1.1\r\nHost: a\r\n{}\r\n", method, headers);
+        let request = parse_request(head.as_bytes()).unwrap();
+        evaluate(&request, &validators())
+    }
+
+    #[test]
+    fn test_validators() {
+        let validators = validators();
+        assert_eq!(validators.etag(), "\"2ebc98a1-400\"");
+        let headers = validators.headers(true);
+        assert_eq!(headers.get("etag"), Some("W/\"2ebc98a1-400\""));
+        assert_eq!(headers.get("last-modified"), Some(MODIFIED));
+        assert!(validators.matches_if_range(MODIFIED));
+        assert!(validators.matches_if_range("\"2ebc98a1-400\""));
+        assert!(!validators.matches_if_range("W/\"2ebc98a1-400\""));
+        assert!(!validators.matches_if_range("Sun, 06 Nov 1994 08:49:38 GMT"));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        use Precondition::*;
+        assert_eq!(evaluate_headers("GET", ""), Proceed);
+        assert_eq!(
+            evaluate_headers("GET", "If-None-Match: \"x\", W/\"2ebc98a1-400\"\r\n"),
+            NotModified
+        );
+        assert_eq!(evaluate_headers("GET", "If-None-Match: \"x\"\r\n"), Proceed);
+        assert_eq!(evaluate_headers("POST", "If-None-Match: *\r\n"), Failed);
+        assert_eq!(
+            evaluate_headers("GET", &format!("If-Modified-Since: {}\r\n", MODIFIED)),
+            NotModified
+        );
+        // If-None-Match takes precedence over If-Modified-Since
+        assert_eq!(
+            evaluate_headers(
+                "GET",
+                &format!(
+                    "If-None-Match: \"x\"\r\nIf-Modified-Since: {}\r\n",
+                    MODIFIED
+                )
+            ),
+            Proceed
+        );
+        assert_eq!(
+            evaluate_headers("GET", "If-Match: W/\"2ebc98a1-400\"\r\n"),
+            Failed
+        );
+        assert_eq!(
+            evaluate_headers("GET", "If-Match: \"2ebc98a1-400\"\r\n"),
+            Proceed
+        );
+        assert_eq!(
+            evaluate_headers(
+                "GET",
+                "If-Unmodified-Since: Sat, 05 Nov 1994 08:49:37 GMT\r\n"
+            ),
+            Failed
+        );
+    }
+
+    #[test]
+    fn test_evaluate_response() {
+        use Precondition::*;
+        let evaluate = |request_headers: &str, response_headers: &str| {
+            let head = format!("GET / HTTP/1.1\r\nHost: a\r\n{}\r\n", request_headers);
+            let response = format!("HTTP/1.1 200 OK\r\n{}\r\n", response_headers);
+            let response = parse_response(response.as_bytes()).unwrap();
+            evaluate_response(&parse_request(head.as_bytes()).unwrap(), &response.headers)
+        };
+        let weak = "ETag: W/\"v1\"\r\n";
+        assert_eq!(
+            evaluate("If-None-Match: \"v0\", \"v1\"\r\n", weak),
+            NotModified
+        );
+        assert_eq!(evaluate("If-None-Match: \"v0\"\r\n", weak), Proceed);
+        assert_eq!(evaluate("If-Match: \"v1\"\r\n", weak), Failed);
+        assert_eq!(evaluate("If-Match: *\r\n", weak), Proceed);
+
+        let modified = format!("Last-Modified: {}\r\n", MODIFIED);
+        assert_eq!(
+            evaluate(&format!("If-Modified-Since: {}\r\n", MODIFIED), &modified),
+            NotModified
+        );
+        assert_eq!(
+            evaluate(
+                "If-Modified-Since: Sat, 05 Nov 1994 08:49:37 GMT\r\n",
+                &modified
+            ),
+            Proceed
+        );
+        assert_eq!(
+            evaluate(&format!("If-Modified-Since: {}\r\n", MODIFIED), ""),
+            Proceed
+        );
+    }
+}
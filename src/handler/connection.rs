@@ -0,0 +1,273 @@
+use std::{io::Error, net::SocketAddr, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, sink},
+    time::timeout,
+};
+
+use crate::{
+    config::ServerConfig,
+    handler::{
+        error_page::send_error,
+        proxy_handler::handle_proxy,
+        static_handler::{handle_static_files, try_files},
+    },
+    http_parser::{
+        body::request_body,
+        reader::{DEFAULT_MAX_HEADER_SIZE, HttpReader},
+        request::{ParseError, Request},
+        uri::normalize,
+    },
+    response_builder::{
+        response::{Response, error_response},
+        status::Status,
+    },
+    router::{
+        location::{Location, LocationHandler},
+        try_files::{Fallback, expand},
+    },
+    server::{Server, VirtualHosts},
+};
+
+pub const DEFAULT_KEEPALIVE_TIMEOUT: u64 = 75; // in s
+pub const DEFAULT_KEEPALIVE_REQUESTS: usize = 1000;
+pub const HEADER_TIMEOUT: Duration = Duration::from_secs(60);
+/// Same limit nginx puts on rewrites and internal redirects
+const MAX_INTERNAL_REDIRECTS: usize = 10;
+
+/// Byte stream of a client connection, plain TCP or TLS
+pub trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> ClientStream for S {}
+
+/// Remote end of a client connection
+#[derive(Debug, Clone, Copy)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    pub scheme: &'static str,
+}
+
+/// Limits applied to every client connection of a server
+#[derive(Debug, Clone)]
+pub struct ConnectionSettings {
+    pub max_header_size: usize, // in b
+    pub keepalive_timeout: Duration,
+    pub keepalive_requests: usize,
+}
+
+impl ConnectionSettings {
+    pub fn new(config: &ServerConfig) -> ConnectionSettings {
+        ConnectionSettings {
+            max_header_size: config
+                .max_header_size
+                .map(|size| size * 1024)
+                .unwrap_or(DEFAULT_MAX_HEADER_SIZE),
+            keepalive_timeout: Duration::from_secs(
+                config
+                    .keepalive_timeout
+                    .unwrap_or(DEFAULT_KEEPALIVE_TIMEOUT),
+            ),
+            keepalive_requests: config
+                .keepalive_requests
+                .unwrap_or(DEFAULT_KEEPALIVE_REQUESTS),
+        }
+    }
+
+    /// Whether another request may follow the `served`-th one on the same connection
+    pub fn allows_next(&self, served: usize) -> bool {
+        !self.keepalive_timeout.is_zero() && served < self.keepalive_requests
+    }
+}
+
+/// Serves requests from one client until either side closes the connection
+pub async fn handle_connection<S: ClientStream>(
+    stream: &mut S,
+    peer: Peer,
+    hosts: &VirtualHosts,
+) -> Result<(), Error> {
+    // the Host header isn't known yet, connection limits come from the default server
+    let settings = &hosts.default_server().settings;
+    let mut reader = HttpReader::new(settings.max_header_size);
+    let mut served = 0;
+    loop {
+        let wait = if served == 0 {
+            HEADER_TIMEOUT
+        } else {
+            settings.keepalive_timeout
+        };
+        let mut request = match timeout(wait, reader.read_request(stream)).await {
+            // idle connection
+            Err(_) => break,
+            Ok(Ok(Some(request))) => request,
+            Ok(Ok(None)) => break,
+            Ok(Err(ParseError::Io(e))) => {
+                eprintln!("Failed to read: {}", e);
+                return Err(e);
+            }
+            Ok(Err(e)) => {
+                if let Some(status) = e.status() {
+                    let response = error_response(status).keep_alive(false);
+                    let _ = response.send(stream, false).await;
+                }
+                let _ = stream.shutdown().await;
+                return Err(Error::other(e.to_string()));
+            }
+        };
+        served += 1;
+        let keep_alive = request.keep_alive() && settings.allows_next(served);
+
+        let server = hosts.find(request.headers.get("host"));
+        let (handler, status) = route(server, &mut request).await;
+        let keep_alive = match handler {
+            Some(LocationHandler::Proxy(proxy)) => {
+                handle_proxy(
+                    stream,
+                    &mut reader,
+                    peer,
+                    server,
+                    proxy,
+                    request,
+                    keep_alive,
+                )
+                .await?
+            }
+            handler => {
+                // bodies are never used here but have to be consumed to reach the next pipelined request
+                reader
+                    .copy_body(stream, &mut sink(), request_body(&request), true)
+                    .await?;
+                match (handler, status) {
+                    (_, Some(status)) if status.is_error() => {
+                        send_error(stream, server, &request, status, keep_alive).await?
+                    }
+                    (_, Some(status)) => {
+                        let head_only = request.method.eq_ignore_ascii_case("head");
+                        Response::new(status)
+                            .empty()
+                            .keep_alive(keep_alive)
+                            .send(stream, head_only)
+                            .await?
+                    }
+                    (Some(LocationHandler::Static { root, .. }), None) => {
+                        handle_static_files(stream, root, server, &request, keep_alive).await?
+                    }
+                    (Some(LocationHandler::Return { status, value }), None) => {
+                        send_return(stream, &request, *status, value, keep_alive).await?
+                    }
+                    _ => send_error(stream, server, &request, Status::NotFound, keep_alive).await?,
+                }
+                keep_alive
+            }
+        };
+        if !keep_alive {
+            break;
+        }
+    }
+
+    let _ = stream.shutdown().await;
+    Ok(())
+}
+
+/// Picks the handler for a request, following `try_files` internal redirects.
+/// A status is returned instead when `try_files` ends in `=code`, redirects loop
+/// or the path climbs above the root.
+async fn route<'a>(
+    server: &'a Server,
+    request: &mut Request,
+) -> (Option<&'a LocationHandler>, Option<Status>) {
+    // `//admin`, `/%61dmin` and `/./admin` all have to match `location /admin/`
+    let Some(uri) = normalize(request.path()) else {
+        return (None, Some(Status::BadRequest));
+    };
+    request.uri = uri;
+    let mut location = server.router.find(&request.uri);
+    for _ in 0..MAX_INTERNAL_REDIRECTS {
+        let Some(Location {
+            handler:
+                LocationHandler::Static {
+                    root,
+                    try_files: Some(rules),
+                },
+            ..
+        }) = location
+        else {
+            return (location.map(|location| &location.handler), None);
+        };
+
+        let uri = request.uri.clone();
+        if let Some(candidate) = try_files(root, rules, &uri).await {
+            request.rewrite(&candidate);
+            return (location.map(|location| &location.handler), None);
+        }
+        location = match &rules.fallback {
+            Fallback::Status(status) => return (None, Some(*status)),
+            Fallback::Uri(fallback) => {
+                request.rewrite(&expand(fallback, &uri));
+                server.router.find(&request.uri)
+            }
+            Fallback::Named(name) => server.router.find_named(name),
+        };
+    }
+
+    eprintln!("Internal redirect cycle while routing {}", request.target);
+    (None, Some(Status::InternalServerError))
+}
+
+async fn send_return<S: ClientStream>(
+    stream: &mut S,
+    request: &Request,
+    status: Status,
+    value: &str,
+    keep_alive: bool,
+) -> Result<(), Error> {
+    let value = value
+        .replace("$request_uri", &request.target)
+        .replace("$uri", &request.uri);
+    let response = if status.is_redirect() {
+        Response::new(status).header("Location", &value).empty()
+    } else {
+        Response::new(status).body("text/plain", value)
+    };
+    let head_only = request.method.eq_ignore_ascii_case("head");
+    response
+        .keep_alive(keep_alive)
+        .send(stream, head_only)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_parser::request::parse_request;
+
+    #[tokio::test]
+    async fn test_route_normalized_uri() {
+        let config: ServerConfig = serde_yaml::from_str(
+            "listen: 0\nroot: /srv\nlocations:\n  - path: /admin/\n    return: \"403\"",
+        )
+        .unwrap();
+        let server = Server::new(&config).unwrap();
+        let route_path = async |path: &str| {
+            let head = format!("GET {}?a=%2F HTTP/1.1\r\nHost: a\r\n\r\n", path);
+            let mut request = parse_request(head.as_bytes()).unwrap();
+            let routed = match route(&server, &mut request).await {
+                (Some(LocationHandler::Return { status, .. }), None) => Some(status.code()),
+                (_, status) => status.map(|status| status.code()),
+            };
+            (routed, request.uri)
+        };
+
+        for path in [
+            "/admin/x",
+            "//admin/x",
+            "/%61dmin/x",
+            "/./admin/x",
+            "/b/../admin/x",
+        ] {
+            assert_eq!(route_path(path).await, (Some(403), "/admin/x".to_string()));
+        }
+        assert_eq!(route_path("/a%20b").await, (None, "/a b".to_string()));
+        assert_eq!(route_path("/../admin/x").await.0, Some(400));
+        assert_eq!(route_path("/%2e%2e/etc/passwd").await.0, Some(400));
+    }
+}
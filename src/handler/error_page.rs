@@ -0,0 +1,160 @@
+use std::{collections::HashMap, io::Error};
+
+use tokio::{fs, time::timeout};
+
+use crate::{
+    config::ServerConfig,
+    handler::{
+        connection::ClientStream,
+        proxy_handler::{PROXY_TIMEOUT, fetch},
+        static_handler::safe_path,
+    },
+    http_parser::request::Request,
+    response_builder::{
+        response::{Response, error_response},
+        status::Status,
+    },
+    router::location::LocationHandler,
+    server::Server,
+};
+
+/// Largest error page body fetched from an upstream
+const MAX_UPSTREAM_PAGE_SIZE: u64 = 1024 * 1024;
+
+/// `error_page` entries of a server, by status code
+#[derive(Debug, Default)]
+pub struct ErrorPages {
+    pages: HashMap<u16, ErrorPage>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ErrorPage {
+    /// Served through the server's locations, from a root or an upstream
+    Uri(String),
+    /// Absolute URL the client is redirected to
+    Redirect(String),
+}
+
+impl ErrorPages {
+    pub fn new(config: &ServerConfig) -> Result<ErrorPages, Error> {
+        let mut pages = HashMap::new();
+        for (code, target) in config.error_page.iter().flatten() {
+            if !(300..600).contains(code) {
+                return Err(Error::other(format!(
+                    "Invalid error_page status {}: expected 300 to 599",
+                    code
+                )));
+            }
+            let page = if target.starts_with("http://") || target.starts_with("https://") {
+                ErrorPage::Redirect(target.clone())
+            } else if target.starts_with('/') {
+                ErrorPage::Uri(target.clone())
+            } else {
+                return Err(Error::other(format!(
+                    "Invalid error_page {}: expected a URI or an http(s) URL",
+                    target
+                )));
+            };
+            pages.insert(*code, page);
+        }
+        Ok(ErrorPages { pages })
+    }
+}
+
+/// Answers with an error status, using the server's `error_page` for it when there is one
+pub async fn send_error<S: ClientStream>(
+    stream: &mut S,
+    server: &Server,
+    request: &Request,
+    status: Status,
+    keep_alive: bool,
+) -> Result<(), Error> {
+    let response = match server.error_pages.pages.get(&status.code()) {
+        Some(ErrorPage::Redirect(url)) => {
+            Response::new(Status::Found).header("Location", url).empty()
+        }
+        Some(ErrorPage::Uri(uri)) => match error_page(server, request, status, uri).await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Error page {} failed: {}", uri, e);
+                error_response(status)
+            }
+        },
+        None => error_response(status),
+    };
+    let head_only = request.method.eq_ignore_ascii_case("head");
+    response
+        .keep_alive(keep_alive)
+        .send(stream, head_only)
+        .await
+}
+
+/// Error pages go through the locations once, they never trigger another error page
+async fn error_page(
+    server: &Server,
+    request: &Request,
+    status: Status,
+    uri: &str,
+) -> Result<Response, Error> {
+    let path = uri.split_once('?').map_or(uri, |(path, _)| path);
+    match server.router.find(path).map(|location| &location.handler) {
+        Some(LocationHandler::Static { root, .. }) => {
+            let file = safe_path(root, path).map_err(|status| {
+                Error::other(format!("{} in {:?} gave {}", path, root, status.code()))
+            })?;
+            let contents = fs::read(&file).await?;
+            Ok(Response::new(status).body(&server.mime_types.content_type(&file), contents))
+        }
+        Some(LocationHandler::Proxy(proxy)) => {
+            let host = request.headers.get("host");
+            let max_header_size = server.settings.max_header_size;
+            let (head, body) = timeout(
+                PROXY_TIMEOUT,
+                fetch(
+                    &proxy.upstream,
+                    uri,
+                    host,
+                    max_header_size,
+                    MAX_UPSTREAM_PAGE_SIZE,
+                ),
+            )
+            .await
+            .map_err(|_| Error::other("timed out"))??;
+            if head.status != 200 {
+                return Err(Error::other(format!("upstream answered {}", head.status)));
+            }
+            let content_type = head
+                .headers
+                .get("content-type")
+                .unwrap_or("text/html")
+                .to_string();
+            Ok(Response::new(status).body(&content_type, body))
+        }
+        _ => Err(Error::other("no location serves it")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_pages(yaml: &str) -> Result<ErrorPages, Error> {
+        let config: ServerConfig = serde_yaml::from_str(&format!("listen: 80\n{}", yaml)).unwrap();
+        ErrorPages::new(&config)
+    }
+
+    #[test]
+    fn test_error_pages() {
+        let pages = error_pages(
+            "error_page:\n  404: /404.html\n  502: /errors/upstream\n  503: https://status.example.com",
+        )
+        .unwrap();
+        assert_eq!(pages.pages[&404], ErrorPage::Uri("/404.html".to_string()));
+        assert_eq!(
+            pages.pages[&503],
+            ErrorPage::Redirect("https://status.example.com".to_string())
+        );
+        assert!(error_pages("error_page:\n  200: /ok.html").is_err());
+        assert!(error_pages("error_page:\n  404: 404.html").is_err());
+    }
+}
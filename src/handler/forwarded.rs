@@ -0,0 +1,224 @@
+use std::{io::Error, net::IpAddr};
+
+use crate::{config::ForwardedConfig, http_parser::headers::Headers};
+
+const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+const X_FORWARDED_PROTO: &str = "X-Forwarded-Proto";
+const X_FORWARDED_HOST: &str = "X-Forwarded-Host";
+const X_REAL_IP: &str = "X-Real-IP";
+const FORWARDED: &str = "Forwarded";
+
+/// Injects client address headers into proxied requests
+#[derive(Debug)]
+pub struct Forwarded {
+    config: ForwardedConfig,
+    trusted_proxies: Vec<IpRange>,
+}
+
+#[derive(Debug, PartialEq)]
+struct IpRange {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Forwarded {
+    pub fn new(config: &ForwardedConfig) -> Result<Forwarded, Error> {
+        let trusted_proxies = config
+            .trusted_proxies
+            .iter()
+            .map(|range| IpRange::parse(range))
+            .collect::<Result<Vec<IpRange>, Error>>()?;
+
+        Ok(Forwarded {
+            config: config.clone(),
+            trusted_proxies,
+        })
+    }
+
+    pub fn apply(&self, headers: &mut Headers, peer: IpAddr, scheme: &str) {
+        let peer = peer.to_canonical();
+        let trusted = self
+            .trusted_proxies
+            .iter()
+            .any(|range| range.contains(peer));
+        if !trusted && self.config.strip_untrusted {
+            for name in [
+                X_FORWARDED_FOR,
+                X_FORWARDED_PROTO,
+                X_FORWARDED_HOST,
+                X_REAL_IP,
+                FORWARDED,
+            ] {
+                headers.remove(name);
+            }
+        }
+        let host = headers.get("host").unwrap_or("").to_string();
+
+        if self.config.x_forwarded_for {
+            let value = match headers.get_joined(X_FORWARDED_FOR) {
+                Some(previous) => format!("{}, {}", previous, peer),
+                None => peer.to_string(),
+            };
+            headers.set(X_FORWARDED_FOR, &value);
+        }
+        if self.config.x_real_ip && headers.get(X_REAL_IP).is_none() {
+            headers.set(X_REAL_IP, &peer.to_string());
+        }
+        if self.config.x_forwarded_proto && headers.get(X_FORWARDED_PROTO).is_none() {
+            headers.set(X_FORWARDED_PROTO, scheme);
+        }
+        if self.config.x_forwarded_host
+            && !host.is_empty()
+            && headers.get(X_FORWARDED_HOST).is_none()
+        {
+            headers.set(X_FORWARDED_HOST, &host);
+        }
+        if self.config.forwarded {
+            let mut element = format!("for={};proto={}", forwarded_node(peer), scheme);
+            if !host.is_empty() {
+                element.push_str(&format!(";host={}", forwarded_value(&host)));
+            }
+            let value = match headers.get_joined(FORWARDED) {
+                Some(previous) => format!("{}, {}", previous, element),
+                None => element,
+            };
+            headers.set(FORWARDED, &value);
+        }
+    }
+}
+
+impl IpRange {
+    /// Parses `10.0.0.0/8`, `::1` or `2001:db8::/32`
+    fn parse(range: &str) -> Result<IpRange, Error> {
+        let invalid = || Error::other(format!("Invalid trusted proxy range: {}", range));
+        let (addr, prefix) = match range.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (range, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().map_err(|_| invalid())?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err(invalid());
+        }
+
+        Ok(IpRange { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(range), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(range) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(range) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Node identifier of RFC 7239 section 6, IPv6 addresses are bracketed and quoted
+fn forwarded_node(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("\"[{}]\"", ip),
+    }
+}
+
+/// Values that aren't plain tokens have to be quoted (RFC 7239 section 4)
+fn forwarded_value(value: &str) -> String {
+    if value
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+    {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_headers() -> Headers {
+        let mut headers = Headers::new();
+        headers.append("Host", "example.com:8081");
+        headers.append("X-Forwarded-For", "1.2.3.4");
+        headers.append("X-Real-IP", "1.2.3.4");
+        headers
+    }
+
+    #[test]
+    fn test_untrusted_client_values_are_stripped() {
+        let forwarded = Forwarded::new(&ForwardedConfig::default()).unwrap();
+        let mut headers = request_headers();
+        forwarded.apply(&mut headers, "192.168.1.7".parse().unwrap(), "http");
+
+        assert_eq!(headers.get("x-forwarded-for"), Some("192.168.1.7"));
+        assert_eq!(headers.get("x-real-ip"), Some("192.168.1.7"));
+        assert_eq!(headers.get("x-forwarded-proto"), Some("http"));
+        assert_eq!(headers.get("x-forwarded-host"), Some("example.com:8081"));
+        assert_eq!(
+            headers.get("forwarded"),
+            Some("for=192.168.1.7;proto=http;host=\"example.com:8081\"")
+        );
+    }
+
+    #[test]
+    fn test_trusted_proxy_values_are_kept() {
+        let forwarded = Forwarded::new(&ForwardedConfig {
+            trusted_proxies: vec!["10.0.0.0/8".to_string()],
+            ..ForwardedConfig::default()
+        })
+        .unwrap();
+        let mut headers = request_headers();
+        forwarded.apply(&mut headers, "10.1.2.3".parse().unwrap(), "http");
+
+        assert_eq!(headers.get("x-forwarded-for"), Some("1.2.3.4, 10.1.2.3"));
+        assert_eq!(headers.get("x-real-ip"), Some("1.2.3.4"));
+
+        // every line of a repeated header is kept, in order
+        let mut headers = request_headers();
+        headers.append("X-Forwarded-For", "5.6.7.8, 9.9.9.9");
+        headers.append("Forwarded", "for=1.2.3.4");
+        headers.append("Forwarded", "for=5.6.7.8");
+        forwarded.apply(&mut headers, "10.1.2.3".parse().unwrap(), "http");
+        assert_eq!(headers.count("x-forwarded-for"), 1);
+        assert_eq!(
+            headers.get("x-forwarded-for"),
+            Some("1.2.3.4, 5.6.7.8, 9.9.9.9, 10.1.2.3")
+        );
+        assert_eq!(
+            headers.get("forwarded"),
+            Some("for=1.2.3.4, for=5.6.7.8, for=10.1.2.3;proto=http;host=\"example.com:8081\"")
+        );
+
+        let mut headers = Headers::new();
+        forwarded.apply(&mut headers, "2001:db8::1".parse().unwrap(), "https");
+        assert_eq!(
+            headers.get("forwarded"),
+            Some("for=\"[2001:db8::1]\";proto=https")
+        );
+    }
+
+    #[test]
+    fn test_ip_range() {
+        let range = IpRange::parse("192.168.0.0/16").unwrap();
+        assert!(range.contains("192.168.44.1".parse().unwrap()));
+        assert!(!range.contains("192.169.0.1".parse().unwrap()));
+        assert!(
+            IpRange::parse("0.0.0.0/0")
+                .unwrap()
+                .contains("8.8.8.8".parse().unwrap())
+        );
+        assert!(IpRange::parse("10.0.0.0/33").is_err());
+        assert!(IpRange::parse("not-an-ip").is_err());
+    }
+}
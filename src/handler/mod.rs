@@ -1,2 +1,9 @@
+pub mod autoindex;
+pub mod conditional;
+pub mod connection;
+pub mod error_page;
+pub mod forwarded;
+pub mod precompressed;
 pub mod proxy_handler;
+pub mod range;
 pub mod static_handler;
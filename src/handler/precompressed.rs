@@ -0,0 +1,111 @@
+use std::{
+    fs::Metadata,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+
+use crate::{
+    compression::accept::AcceptEncoding,
+    config::ServerConfig,
+    constants::encodings::{BROTLI, GZIP, ZSTD},
+};
+
+/// Sibling files like `app.js.br` written by a build step, served instead of compressing on the fly
+#[derive(Debug, Default)]
+pub struct Precompressed {
+    // (encoding, file extension), in order of preference
+    variants: Vec<(&'static str, &'static str)>,
+}
+
+/// A precompressed file found for a request
+pub struct Sidecar {
+    pub encoding: &'static str,
+    pub path: PathBuf,
+    pub metadata: Metadata,
+}
+
+impl Precompressed {
+    pub fn new(config: &ServerConfig) -> Precompressed {
+        let variants = [
+            (config.brotli_static, BROTLI, "br"),
+            (config.zstd_static, ZSTD, "zst"),
+            (config.gzip_static, GZIP, "gz"),
+        ]
+        .into_iter()
+        .filter(|(enabled, _, _)| enabled.unwrap_or(false))
+        .map(|(_, encoding, extension)| (encoding, extension))
+        .collect();
+        Precompressed { variants }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.variants.is_empty()
+    }
+
+    /// Existing sidecar of `path` in the encoding the client prefers
+    pub async fn find(&self, path: &Path, accepted: &AcceptEncoding) -> Option<Sidecar> {
+        for (encoding, extension) in accepted.ranked(self.variants.iter(), |(encoding, _)| encoding)
+        {
+            let mut sidecar = path.as_os_str().to_owned();
+            sidecar.push(".");
+            sidecar.push(extension);
+            let sidecar = PathBuf::from(sidecar);
+            if let Ok(metadata) = fs::metadata(&sidecar).await
+                && metadata.is_file()
+            {
+                return Some(Sidecar {
+                    encoding,
+                    path: sidecar,
+                    metadata,
+                });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_parser::headers::Headers;
+
+    #[tokio::test]
+    async fn test_find_sidecar() {
+        let dir =
+            std::env::temp_dir().join(format!("rs-ngnix-precompressed-{}", std::process::id()));
+        fs::create_dir_all(&dir).await.unwrap();
+        let file = dir.join("app.js");
+        fs::write(&file, "plain").await.unwrap();
+        fs::write(dir.join("app.js.gz"), "gz").await.unwrap();
+        fs::write(dir.join("app.js.br"), "br").await.unwrap();
+
+        let config: ServerConfig =
+            serde_yaml::from_str("listen: 80\ngzip_static: true\nbrotli_static: true").unwrap();
+        let precompressed = Precompressed::new(&config);
+
+        let accept = |value: &str| {
+            let mut headers = Headers::new();
+            headers.append("Accept-Encoding", value);
+            AcceptEncoding::new(&headers)
+        };
+        let sidecar = precompressed
+            .find(&file, &accept("gzip, br"))
+            .await
+            .unwrap();
+        assert_eq!(sidecar.encoding, BROTLI);
+        assert_eq!(sidecar.path, dir.join("app.js.br"));
+        assert_eq!(sidecar.metadata.len(), 2);
+        let sidecar = precompressed
+            .find(&file, &accept("GZIP, zstd, br;q=0.5"))
+            .await
+            .unwrap();
+        assert_eq!(sidecar.encoding, GZIP);
+        assert!(precompressed.find(&file, &accept("zstd")).await.is_none());
+
+        let disabled: ServerConfig = serde_yaml::from_str("listen: 80").unwrap();
+        assert!(Precompressed::new(&disabled).variants.is_empty());
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -1,24 +1,692 @@
-use std::io::Error;
+use std::{
+    io::{Error, ErrorKind},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
 
-use tokio::{io::copy, net::TcpStream, try_join};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    time::{Instant, Sleep, sleep, timeout},
+};
 
-pub async fn handle_proxy(
-    request_stream: &mut TcpStream,
-    proxy_address: &String,
+use crate::{
+    cache::{
+        control::{requires_revalidation, storable},
+        proxy::{CacheStatus, CachedResponse, ProxyCache},
+    },
+    config::UpstreamConfig,
+    handler::{
+        conditional::{Precondition, evaluate_response},
+        connection::{ClientStream, ConnectionSettings, Peer},
+        error_page::send_error,
+    },
+    http_parser::{
+        body::{Body, request_body, response_body},
+        headers::Headers,
+        reader::{DEFAULT_MAX_HEADER_SIZE, HttpReader},
+        request::{ParseError, Request, Version},
+        response::ResponseHead,
+    },
+    load_balancer::{pool::UpstreamConnection, upstream::Upstream},
+    response_builder::{response::connection_header, status::Status},
+    server::Server,
+};
+
+pub const PROXY_TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest wait between two reads of a request body
+const CLIENT_BODY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Methods whose effect is the same when sent twice (RFC 9110 section 9.2.2)
+const IDEMPOTENT_METHODS: [&str; 6] = ["GET", "HEAD", "PUT", "DELETE", "OPTIONS", "TRACE"];
+
+/// Headers describing a single connection, never forwarded (RFC 9110 section 7.6.1)
+const HOP_BY_HOP_HEADERS: [&str; 6] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "upgrade",
+];
+
+/// Backends of a proxy location, and the cache of their responses
+pub struct Proxy {
+    pub upstream: Upstream,
+    pub cache: Option<ProxyCache>,
+}
+
+/// Where the request's response would be stored, and what is stored there already
+struct Lookup<'a> {
+    cache: &'a ProxyCache,
+    key: String,
+    stored: Option<Arc<CachedResponse>>,
+}
+
+impl Proxy {
+    /// `None` without backends
+    pub fn new(config: &UpstreamConfig) -> Result<Option<Proxy>, Error> {
+        let Some(upstream) = Upstream::new(config) else {
+            return Ok(None);
+        };
+        Ok(Some(Proxy {
+            upstream,
+            cache: ProxyCache::new(config)?,
+        }))
+    }
+
+    /// Only bodyless GET and HEAD requests go through the cache
+    async fn lookup(&self, request: &Request, peer: Peer) -> Option<Lookup<'_>> {
+        let cache = self.cache.as_ref()?;
+        let method = request.method.as_str();
+        if !(method.eq_ignore_ascii_case("get") || method.eq_ignore_ascii_case("head"))
+            || request_body(request) != Body::Empty
+        {
+            return None;
+        }
+        let key = cache.key(request, peer.scheme);
+        let stored = cache.get(&key, request).await;
+        Some(Lookup { cache, key, stored })
+    }
+}
+
+/// Proxies one request, returns whether the client connection can be reused
+pub async fn handle_proxy<S: ClientStream>(
+    stream: &mut S,
+    reader: &mut HttpReader,
+    peer: Peer,
+    server: &Server,
+    proxy: &Proxy,
+    mut request: Request,
+    keep_alive: bool,
+) -> Result<bool, Error> {
+    let upstream = &proxy.upstream;
+    let lookup = proxy.lookup(&request, peer).await;
+    let stored = lookup.as_ref().and_then(|lookup| lookup.stored.clone());
+    let head_only = request.method.eq_ignore_ascii_case("head");
+    if let Some(stored) = &stored
+        && stored.is_fresh()
+        && !requires_revalidation(&request)
+    {
+        println!("Proxy cache hit {}", request.target);
+        send_cached(stream, &request, stored, CacheStatus::Hit, keep_alive).await?;
+        return Ok(keep_alive);
+    }
+    // the client's own conditions are answered from the stored response, not by the upstream
+    let client_request = request.clone();
+    // a stale response that may stand in when the upstream can't answer
+    let stale = stored.clone().filter(|stored| stored.serves_stale());
+
+    // the stored response is checked with the upstream in place of the client's own conditions
+    let revalidating = match &stored {
+        Some(stored) if !head_only => {
+            let conditions = stored.conditional_headers();
+            let revalidating = conditions.iter().next().is_some();
+            if revalidating {
+                request.headers.remove("if-none-match");
+                request.headers.remove("if-modified-since");
+                for (name, value) in conditions.iter() {
+                    request.headers.append(name, value);
+                }
+            }
+            revalidating
+        }
+        _ => false,
+    };
+
+    if let Some(forwarded) = &server.forwarded {
+        forwarded.apply(&mut request.headers, peer.addr.ip(), peer.scheme);
+    }
+
+    let Some(current) = upstream.get_healthy_server().await else {
+        println!("No live server found");
+        if let Some(stale) = &stale {
+            send_cached(
+                stream,
+                &client_request,
+                stale,
+                CacheStatus::Stale,
+                keep_alive,
+            )
+            .await?;
+            return Ok(keep_alive);
+        }
+        let _ = send_error(stream, server, &request, Status::ServiceUnavailable, false).await;
+        return Ok(false);
+    };
+    let proxy_address = &upstream.addresses[current];
+    println!(
+        "Received Proxy request {} {}, proxying to {}",
+        request.method, request.target, proxy_address
+    );
+
+    // answered here so clients don't hold the body back waiting on the upstream
+    if request.headers.has_token("expect", "100-continue") {
+        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+        stream.flush().await?;
+    }
+
+    let exchanged = exchange(
+        stream,
+        reader,
+        &request,
+        upstream,
+        current,
+        &server.settings,
+    )
+    .await;
+    let (mut connection, response) = match (exchanged, &stale) {
+        (Ok(exchanged), _) => exchanged,
+        // nothing the upstream did, the request body was malformed, cut short or too slow
+        (Err(ExchangeError::Client(e)), _) => {
+            let status = match e.kind() {
+                ErrorKind::TimedOut => Status::RequestTimeout,
+                _ => Status::BadRequest,
+            };
+            let _ = send_error(stream, server, &request, status, false).await;
+            return Err(e);
+        }
+        (Err(ExchangeError::Upstream(e) | ExchangeError::Unanswered(e)), Some(stale)) => {
+            eprintln!("{} failed ({}), serving a stale response", proxy_address, e);
+            send_cached(
+                stream,
+                &client_request,
+                stale,
+                CacheStatus::Stale,
+                keep_alive,
+            )
+            .await?;
+            return Ok(keep_alive);
+        }
+        (Err(ExchangeError::Upstream(e) | ExchangeError::Unanswered(e)), None) => {
+            let status = match e.kind() {
+                ErrorKind::TimedOut => Status::GatewayTimeout,
+                _ => Status::BadGateway,
+            };
+            let _ = send_error(stream, server, &request, status, false).await;
+            return Err(e);
+        }
+    };
+
+    let upstream_keep_alive = upstream.keeps_connections() && response.keep_alive();
+    if let Some(lookup) = &lookup
+        && let Some(stored) = &stored
+    {
+        if revalidating && response.status == 304 {
+            if upstream_keep_alive {
+                upstream.release(current, connection).await;
+            }
+            let status = CacheStatus::Revalidated;
+            match stored.revalidated(&request, &response) {
+                Some(refreshed) => {
+                    send_cached(stream, &client_request, &refreshed, status, keep_alive).await?;
+                    lookup.cache.insert(&lookup.key, &request, refreshed).await;
+                }
+                // the upstream no longer allows storing it
+                None => {
+                    send_cached(stream, &client_request, stored, status, keep_alive).await?;
+                    lookup.cache.remove(&lookup.key, &request).await;
+                }
+            }
+            return Ok(keep_alive);
+        }
+        // the connection is dropped along with the error body
+        if response.status >= 500
+            && let Some(stale) = &stale
+        {
+            eprintln!(
+                "{} answered {}, serving a stale response",
+                proxy_address, response.status
+            );
+            send_cached(
+                stream,
+                &client_request,
+                stale,
+                CacheStatus::Stale,
+                keep_alive,
+            )
+            .await?;
+            return Ok(keep_alive);
+        }
+    }
+
+    let cache_status = lookup.as_ref().map(|lookup| match lookup.stored {
+        Some(_) => CacheStatus::Expired,
+        None => CacheStatus::Miss,
+    });
+    let freshness = lookup.as_ref().and_then(|_| storable(&request, &response));
+    let collect = lookup
+        .as_ref()
+        .filter(|_| freshness.is_some())
+        .map(|lookup| lookup.cache.max_entry_size());
+    let (keep_alive, body, collected) = send_response(
+        stream,
+        &request,
+        &response,
+        &mut connection,
+        keep_alive,
+        cache_status,
+        collect,
+    )
+    .await?;
+    if upstream_keep_alive && body != Body::UntilClose {
+        upstream.release(current, connection).await;
+    }
+    if let Some(lookup) = &lookup
+        && let Some(freshness) = freshness
+        && let Some(collected) = collected
+    {
+        let stored = CachedResponse::new(&response, collected, freshness);
+        lookup.cache.insert(&lookup.key, &request, stored).await;
+    }
+    Ok(keep_alive)
+}
+
+/// Answers `request` from a stored response, or with a 304 or 412 when its conditions say so
+async fn send_cached<S: ClientStream>(
+    stream: &mut S,
+    request: &Request,
+    response: &CachedResponse,
+    cache_status: CacheStatus,
+    keep_alive: bool,
 ) -> Result<(), Error> {
-    let client_stream_result = TcpStream::connect(proxy_address).await;
-    if let Ok(client_stream) = client_stream_result {
-        let mut client_stream = client_stream;
-        let (mut client_read_stream, mut client_write_stream) = client_stream.split();
-        let (mut request_read_stream, mut request_write_stream) = request_stream.split();
-        // println!("RW streams created");
-        let request_client = copy(&mut request_read_stream, &mut client_write_stream);
-        let client_request = copy(&mut client_read_stream, &mut request_write_stream);
-
-        let _ = try_join!(request_client, client_request);
-        Ok(())
-    } else {
-        //throw error
-        Err(client_stream_result.err().unwrap())
+    let precondition = evaluate_response(request, &response.headers);
+    let mut head = match precondition {
+        Precondition::Proceed => {
+            let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, response.reason);
+            write_end_to_end_headers(&mut head, &response.headers, &[]);
+            head
+        }
+        Precondition::NotModified => {
+            let mut head = "HTTP/1.1 304 Not Modified\r\n".to_string();
+            write_end_to_end_headers(&mut head, &response.headers, &["content-length"]);
+            head
+        }
+        Precondition::Failed => {
+            "HTTP/1.1 412 Precondition Failed\r\nContent-Length: 0\r\n".to_string()
+        }
+    };
+    head.push_str(&format!(
+        "Age: {}\r\nX-Cache-Status: {}\r\nConnection: {}\r\n\r\n",
+        response.age().as_secs(),
+        cache_status.as_str(),
+        connection_header(keep_alive)
+    ));
+    stream.write_all(head.as_bytes()).await?;
+    let head_only = request.method.eq_ignore_ascii_case("head");
+    if !head_only && precondition == Precondition::Proceed {
+        stream.write_all(&response.body).await?;
+    }
+    stream.flush().await
+}
+
+/// Which side of the proxy an exchange failed on
+enum ExchangeError {
+    Client(Error), // reading the request body
+    Upstream(Error),
+    Unanswered(Error), // the upstream failed before sending any response bytes
+}
+
+impl ExchangeError {
+    fn into_error(self) -> Error {
+        match self {
+            ExchangeError::Client(e)
+            | ExchangeError::Upstream(e)
+            | ExchangeError::Unanswered(e) => e,
+        }
+    }
+}
+
+/// Sends the request over a pooled or new upstream connection.
+/// Bodyless idempotent requests are retried once on a new connection when a reused one fails unanswered,
+/// the upstream may close an idle connection right after `ConnectionPool::take` checked it.
+async fn exchange<S: ClientStream>(
+    stream: &mut S,
+    reader: &mut HttpReader,
+    request: &Request,
+    upstream: &Upstream,
+    current: usize,
+    settings: &ConnectionSettings,
+) -> Result<(UpstreamConnection, ResponseHead), ExchangeError> {
+    let proxy_address = &upstream.addresses[current];
+    let keep_alive = upstream.keeps_connections();
+
+    if let Some(mut connection) = upstream.take_connection(current).await {
+        match send_request(
+            stream,
+            reader,
+            request,
+            &mut connection,
+            proxy_address,
+            keep_alive,
+        )
+        .await
+        {
+            Ok(response) => return Ok((connection, response)),
+            Err(ExchangeError::Unanswered(e)) if connection.reused && can_retry(request) => {
+                println!(
+                    "Pooled connection to {} failed ({}), retrying",
+                    proxy_address, e
+                )
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut connection = upstream_timeout(upstream.connect(current, settings.max_header_size))
+        .await
+        .map_err(ExchangeError::Upstream)?;
+    let response = send_request(
+        stream,
+        reader,
+        request,
+        &mut connection,
+        proxy_address,
+        keep_alive,
+    )
+    .await?;
+    Ok((connection, response))
+}
+
+/// Forwards the request head and body, then waits for the final response head
+async fn send_request<S: ClientStream>(
+    stream: &mut S,
+    reader: &mut HttpReader,
+    request: &Request,
+    connection: &mut UpstreamConnection,
+    proxy_address: &str,
+    keep_alive: bool,
+) -> Result<ResponseHead, ExchangeError> {
+    let mut head = format!("{} {} HTTP/1.1\r\n", request.method, request.target);
+    if request.headers.get("host").is_none() {
+        head.push_str(&format!("Host: {}\r\n", proxy_address));
+    }
+    write_end_to_end_headers(&mut head, &request.headers, &["expect"]);
+    head.push_str(&format!(
+        "Connection: {}\r\n\r\n",
+        connection_header(keep_alive)
+    ));
+    connection
+        .stream
+        .write_all(head.as_bytes())
+        .await
+        .map_err(ExchangeError::Unanswered)?;
+
+    // copy errors are the client's unless writing to the upstream failed
+    let mut upstream_writer = Tracked {
+        inner: &mut connection.stream,
+        failed: false,
+    };
+    let mut client = TimedRead::new(stream, CLIENT_BODY_TIMEOUT);
+    let copied = reader
+        .copy_body(
+            &mut client,
+            &mut upstream_writer,
+            request_body(request),
+            false,
+        )
+        .await;
+    match (copied, upstream_writer.failed) {
+        (Ok(()), _) => {}
+        (Err(e), true) => return Err(ExchangeError::Upstream(e)),
+        (Err(e), false) => return Err(ExchangeError::Client(e)),
+    }
+    connection
+        .stream
+        .flush()
+        .await
+        .map_err(ExchangeError::Unanswered)?;
+
+    read_final_response(connection).await
+}
+
+/// Whether the request can be sent again, its body is gone once forwarded (RFC 9110 section 9.2.2)
+fn can_retry(request: &Request) -> bool {
+    IDEMPOTENT_METHODS.contains(&request.method.as_str()) && request_body(request) == Body::Empty
+}
+
+/// Runs a step that waits on the upstream, failing with `TimedOut` after `PROXY_TIMEOUT`
+async fn upstream_timeout<T>(step: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+    timeout(PROXY_TIMEOUT, step)
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "Upstream timed out"))?
+}
+
+/// Skips interim responses, 100 Continue is sent to the client by `handle_proxy`
+async fn read_final_response(
+    connection: &mut UpstreamConnection,
+) -> Result<ResponseHead, ExchangeError> {
+    let mut interim = false;
+    let read = upstream_timeout(async {
+        loop {
+            match connection
+                .reader
+                .read_response(&mut connection.stream)
+                .await
+            {
+                Ok(Some(response)) if response.status / 100 == 1 => interim = true,
+                Ok(Some(response)) => return Ok(response),
+                Ok(None) => return Err(Error::other("Upstream closed the connection")),
+                Err(ParseError::Io(e)) => return Err(e),
+                Err(e) => return Err(Error::other(e.to_string())),
+            }
+        }
+    })
+    .await;
+    read.map_err(|e| {
+        if !interim && connection.reader.is_empty() && e.kind() != ErrorKind::TimedOut {
+            ExchangeError::Unanswered(e)
+        } else {
+            ExchangeError::Upstream(e)
+        }
+    })
+}
+
+/// Streams the response back, returns whether the client connection can be reused.
+/// With `collect`, the body is also returned when it isn't larger.
+async fn send_response<S: ClientStream>(
+    stream: &mut S,
+    request: &Request,
+    response: &ResponseHead,
+    connection: &mut UpstreamConnection,
+    keep_alive: bool,
+    cache_status: Option<CacheStatus>,
+    collect: Option<usize>,
+) -> Result<(bool, Body, Option<Vec<u8>>), Error> {
+    let body = response_body(&request.method, response);
+    // HTTP/1.0 clients can't read chunked bodies, they get the raw data until close
+    let dechunk = body == Body::Chunked && request.version == Version::Http10;
+    let keep_alive = keep_alive && body != Body::UntilClose && !dechunk;
+
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, response.reason);
+    let skipped: &[&str] = if dechunk { &["transfer-encoding"] } else { &[] };
+    write_end_to_end_headers(&mut head, &response.headers, skipped);
+    if let Some(cache_status) = cache_status {
+        head.push_str(&format!("X-Cache-Status: {}\r\n", cache_status.as_str()));
+    }
+    head.push_str(&format!(
+        "Connection: {}\r\n\r\n",
+        connection_header(keep_alive)
+    ));
+    stream.write_all(head.as_bytes()).await?;
+
+    let mut tee = Tee {
+        inner: stream,
+        copy: collect.map(|_| Vec::new()),
+        limit: collect.unwrap_or(0),
+    };
+    connection
+        .reader
+        .copy_body(&mut connection.stream, &mut tee, body, dechunk)
+        .await?;
+    tee.flush().await?;
+
+    let collected = match tee.copy {
+        // stored without the chunked framing
+        Some(copy) if body == Body::Chunked && !dechunk => {
+            let mut decoded = Vec::new();
+            HttpReader::new(DEFAULT_MAX_HEADER_SIZE)
+                .copy_body(&mut copy.as_slice(), &mut decoded, Body::Chunked, true)
+                .await
+                .ok()
+                .map(|_| decoded)
+        }
+        copy => copy,
+    };
+    Ok((keep_alive, body, collected))
+}
+
+/// Passes writes through, keeping a copy of them until it would grow past `limit`
+struct Tee<'a, W> {
+    inner: &'a mut W,
+    copy: Option<Vec<u8>>,
+    limit: usize,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Tee<'_, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
+        let tee = &mut *self;
+        let written = ready!(Pin::new(&mut *tee.inner).poll_write(cx, buf))?;
+        if let Some(copy) = &mut tee.copy {
+            if copy.len() + written > tee.limit {
+                tee.copy = None;
+            } else {
+                copy.extend_from_slice(&buf[..written]);
+            }
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut *self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut *self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Fails reads with `TimedOut` when no data comes in for `timeout`, like nginx `client_body_timeout`
+struct TimedRead<'a, R> {
+    inner: &'a mut R,
+    timeout: Duration,
+    deadline: Pin<Box<Sleep>>,
+}
+
+impl<'a, R> TimedRead<'a, R> {
+    fn new(inner: &'a mut R, timeout: Duration) -> TimedRead<'a, R> {
+        TimedRead {
+            inner,
+            timeout,
+            deadline: Box::pin(sleep(timeout)),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TimedRead<'_, R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), Error>> {
+        let timed = &mut *self;
+        if let Poll::Ready(result) = Pin::new(&mut *timed.inner).poll_read(cx, buf) {
+            let next = Instant::now() + timed.timeout;
+            timed.deadline.as_mut().reset(next);
+            return Poll::Ready(result);
+        }
+        ready!(timed.deadline.as_mut().poll(cx));
+        Poll::Ready(Err(Error::new(
+            ErrorKind::TimedOut,
+            "Client sent no request body data in time",
+        )))
+    }
+}
+
+/// Passes writes through, remembering whether one of them failed
+struct Tracked<'a, W> {
+    inner: &'a mut W,
+    failed: bool,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Tracked<'_, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
+        let result = ready!(Pin::new(&mut *self.inner).poll_write(cx, buf));
+        self.failed |= result.is_err();
+        Poll::Ready(result)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let result = ready!(Pin::new(&mut *self.inner).poll_flush(cx));
+        self.failed |= result.is_err();
+        Poll::Ready(result)
     }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let result = ready!(Pin::new(&mut *self.inner).poll_shutdown(cx));
+        self.failed |= result.is_err();
+        Poll::Ready(result)
+    }
+}
+
+fn write_end_to_end_headers(head: &mut String, headers: &Headers, skipped: &[&str]) {
+    for (name, value) in headers.iter() {
+        let lower_name = name.to_ascii_lowercase();
+        if HOP_BY_HOP_HEADERS.contains(&lower_name.as_str())
+            || skipped.contains(&lower_name.as_str())
+            || headers.has_token("connection", name)
+        {
+            continue;
+        }
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+}
+
+/// GETs `uri` on a new connection, for error pages served from an upstream.
+/// At most `max_size` bytes are read after the head.
+pub async fn fetch(
+    upstream: &Upstream,
+    uri: &str,
+    host: Option<&str>,
+    max_header_size: usize,
+    max_size: u64,
+) -> Result<(ResponseHead, Vec<u8>), Error> {
+    let current = upstream
+        .get_healthy_server()
+        .await
+        .ok_or_else(|| Error::other("No live server found"))?;
+    let proxy_address = &upstream.addresses[current];
+    let mut connection = upstream.connect(current, max_header_size).await?;
+    let head = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        uri,
+        host.unwrap_or(proxy_address)
+    );
+    connection.stream.write_all(head.as_bytes()).await?;
+    connection.stream.flush().await?;
+
+    let response = read_final_response(&mut connection)
+        .await
+        .map_err(ExchangeError::into_error)?;
+    let mut body = Vec::new();
+    let mut limited = (&mut connection.stream).take(max_size);
+    connection
+        .reader
+        .copy_body(
+            &mut limited,
+            &mut body,
+            response_body("GET", &response),
+            true,
+        )
+        .await?;
+    Ok((response, body))
 }
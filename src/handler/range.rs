@@ -0,0 +1,288 @@
+use std::{
+    io::{Error, SeekFrom},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, copy},
+};
+
+use crate::{
+    handler::{conditional::Validators, connection::ClientStream},
+    http_parser::{headers::Headers, request::Request},
+    response_builder::{response::Response, status::Status},
+};
+
+/// More ranges than this in one request are ignored and the whole file is sent
+const MAX_RANGES: usize = 16;
+
+/// Inclusive byte positions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+    Full,
+    Partial(Vec<ByteRange>),
+    Unsatisfiable,
+}
+
+pub enum RangeSource<'a> {
+    Memory(&'a [u8]),
+    File(&'a mut File),
+}
+
+impl ByteRange {
+    fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+/// Ranges to serve for a GET request, `If-Range` is checked against the file's validators
+pub fn requested_range(
+    request: &Request,
+    size: u64,
+    validators: Option<&Validators>,
+) -> RangeRequest {
+    if !request.method.eq_ignore_ascii_case("get") {
+        return RangeRequest::Full;
+    }
+    let Some(range) = request.headers.get("range") else {
+        return RangeRequest::Full;
+    };
+    if let Some(if_range) = request.headers.get("if-range")
+        && !validators.is_some_and(|validators| validators.matches_if_range(if_range))
+    {
+        return RangeRequest::Full;
+    }
+    parse_range(range, size)
+}
+
+/// Parses `bytes=0-99,200-,-50`. Invalid headers are ignored as RFC 9110 section 14.2 allows.
+pub fn parse_range(range: &str, size: u64) -> RangeRequest {
+    let Some((unit, specs)) = range.split_once('=') else {
+        return RangeRequest::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        let Some((first, last)) = spec.trim().split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+        let parse = |value: &str| value.parse::<u64>().ok();
+
+        let range = if first.is_empty() {
+            // suffix range, the last n bytes
+            let Some(suffix) = parse(last) else {
+                return RangeRequest::Full;
+            };
+            (suffix > 0 && size > 0).then(|| ByteRange {
+                start: size.saturating_sub(suffix),
+                end: size - 1,
+            })
+        } else {
+            let Some(start) = parse(first) else {
+                return RangeRequest::Full;
+            };
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                match parse(last) {
+                    Some(end) if end >= start => end,
+                    _ => return RangeRequest::Full,
+                }
+            };
+            (start < size).then(|| ByteRange {
+                start,
+                end: end.min(size - 1),
+            })
+        };
+        ranges.extend(range);
+    }
+
+    if specs.split(',').count() > MAX_RANGES {
+        RangeRequest::Full
+    } else if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(ranges)
+    }
+}
+
+/// Writes a 206 response, as `multipart/byteranges` when more than one range was asked for
+pub async fn send_ranges<S: ClientStream>(
+    stream: &mut S,
+    ranges: &[ByteRange],
+    size: u64,
+    content_type: &str,
+    extra_headers: &Headers,
+    keep_alive: bool,
+    mut source: RangeSource<'_>,
+) -> Result<(), Error> {
+    if let [range] = ranges {
+        Response::new(Status::PartialContent)
+            .content_length(range.len())
+            .header("Content-Type", content_type)
+            .header("Content-Range", &range.content_range(size))
+            .headers(extra_headers)
+            .header("Accept-Ranges", "bytes")
+            .keep_alive(keep_alive)
+            .write_head(stream)
+            .await?;
+        write_range(stream, range, &mut source).await?;
+        return stream.flush().await;
+    }
+
+    let boundary = format!(
+        "{:016x}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    );
+    let part_heads: Vec<String> = ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                boundary,
+                content_type,
+                range.content_range(size)
+            )
+        })
+        .collect();
+    let closing = format!("\r\n--{}--\r\n", boundary);
+    let content_length = part_heads.iter().map(|head| head.len() as u64).sum::<u64>()
+        + ranges.iter().map(ByteRange::len).sum::<u64>()
+        + closing.len() as u64;
+
+    Response::new(Status::PartialContent)
+        .content_length(content_length)
+        .header(
+            "Content-Type",
+            &format!("multipart/byteranges; boundary={}", boundary),
+        )
+        .headers(extra_headers)
+        .header("Accept-Ranges", "bytes")
+        .keep_alive(keep_alive)
+        .write_head(stream)
+        .await?;
+    for (range, head) in ranges.iter().zip(part_heads) {
+        stream.write_all(head.as_bytes()).await?;
+        write_range(stream, range, &mut source).await?;
+    }
+    stream.write_all(closing.as_bytes()).await?;
+    stream.flush().await
+}
+
+pub async fn send_unsatisfiable<S: ClientStream>(
+    stream: &mut S,
+    size: u64,
+    keep_alive: bool,
+) -> Result<(), Error> {
+    Response::new(Status::RangeNotSatisfiable)
+        .empty()
+        .header("Content-Range", &format!("bytes */{}", size))
+        .keep_alive(keep_alive)
+        .send(stream, true)
+        .await
+}
+
+async fn write_range<S: ClientStream>(
+    stream: &mut S,
+    range: &ByteRange,
+    source: &mut RangeSource<'_>,
+) -> Result<(), Error> {
+    match source {
+        RangeSource::Memory(data) => {
+            stream
+                .write_all(&data[range.start as usize..=range.end as usize])
+                .await
+        }
+        RangeSource::File(file) => {
+            file.seek(SeekFrom::Start(range.start)).await?;
+            let copied = copy(&mut (&mut **file).take(range.len()), stream).await?;
+            if copied < range.len() {
+                return Err(Error::other("File shrank while being sent"));
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            RangeRequest::Partial(vec![range(0, 99)])
+        );
+        assert_eq!(
+            parse_range("bytes=900-, -50, 10-2000", 1000),
+            RangeRequest::Partial(vec![range(900, 999), range(950, 999), range(10, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeRequest::Partial(vec![range(0, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=5-1", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("items=0-1", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=a-b", 1000), RangeRequest::Full);
+    }
+
+    #[tokio::test]
+    async fn test_send_multipart_ranges() {
+        let data = b"0123456789";
+        let (mut server, mut client) = tokio::io::duplex(4096);
+        send_ranges(
+            &mut server,
+            &[range(0, 1), range(8, 9)],
+            10,
+            "text/plain",
+            &Headers::new(),
+            true,
+            RangeSource::Memory(data),
+        )
+        .await
+        .unwrap();
+        drop(server);
+
+        let mut output = String::new();
+        client.read_to_string(&mut output).await.unwrap();
+        let (head, body) = output.split_once("\r\n\r\n").unwrap();
+        let length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(body.len(), length);
+        assert!(body.contains("Content-Range: bytes 0-1/10\r\n\r\n01\r\n"));
+        assert!(body.contains("Content-Range: bytes 8-9/10\r\n\r\n89\r\n"));
+        assert!(body.ends_with("--\r\n"));
+    }
+}
@@ -1,210 +1,676 @@
 use std::{
     fs::Metadata,
-    io::Error,
+    io::{Error, ErrorKind},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::Mutex,
+    time::Instant,
 };
 
 use tokio::{
     fs::{self, File},
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+    io::{AsyncReadExt, AsyncWriteExt, copy},
 };
 
 use crate::{
-    cache::lru::Cache,
-    compression::gzip::{Encoding, compress_stream},
-    constants::encodings::GZIP,
-    response_builder::http::{
-        BAD_REQUEST_RESPONSE, NOT_FOUND_RESPONSE, create_response, get_file_type,
+    cache::entry::{CacheEntry, CacheKey},
+    compression::{
+        accept::AcceptEncoding,
+        encoder::{Encoding, compress, compress_stream},
     },
+    handler::{
+        conditional::{Validators, evaluate, send_precondition},
+        connection::ClientStream,
+        error_page::send_error,
+        precompressed::Sidecar,
+        range::{RangeRequest, RangeSource, requested_range, send_ranges, send_unsatisfiable},
+    },
+    http_parser::{
+        headers::Headers,
+        request::{Request, Version},
+        uri::percent_encode,
+    },
+    response_builder::{response::Response, status::Status},
+    router::try_files::{TryFiles, expand},
+    server::Server,
 };
 
-pub async fn handle_static_files(
-    stream: &mut TcpStream,
+pub const DEFAULT_INDEX: [&str; 2] = ["index.html", "index.htm"];
+
+pub async fn handle_static_files<S: ClientStream>(
+    stream: &mut S,
     root: &Path,
-    cache: &Arc<Cache>,
+    server: &Server,
+    request: &Request,
+    keep_alive: bool,
 ) -> Result<(), Error> {
-    let mut buff = [0; 1024];
+    let cache = &server.cache;
+    let mime_types = &server.mime_types;
+    let method = request.method.as_str();
+    let head_only = method.eq_ignore_ascii_case("head");
+    let requested_path = request.uri.as_str();
+    let accept_encoding = AcceptEncoding::new(&request.headers);
 
-    let n = match stream.read(&mut buff).await {
-        Ok(0) => {
-            return Err(Error::other("Empty buffer"));
-        }
-        Ok(n) => n,
-        Err(e) => {
-            eprintln!("Failed to read: {}", e);
-            return Err(Error::other(format!("Error {}", e)));
+    println!(
+        "Method {}, Path {}, Version {:?}",
+        method, requested_path, request.version
+    );
+    println!("Encodings supported {:?}", accept_encoding);
+    //checking cached response
+
+    let mut path = match safe_path(root, requested_path) {
+        Ok(path) => path,
+        Err(status) => {
+            eprintln!("Invalid path requested: {}", requested_path);
+            return send_error(stream, server, request, status, keep_alive).await;
         }
     };
-    let request = String::from_utf8_lossy(&buff[..n]);
-    let request_line = request.lines().next().unwrap_or("");
-    let mut parts = request_line.split_whitespace();
-    let method = parts.next().unwrap_or("");
-    let requested_path = parts.next().unwrap_or("/");
-    let mut encodings: Vec<&str> = Vec::new();
-    for line in request.lines() {
-        if line.to_lowercase().contains("accept-encoding: ") {
-            let mut encoding_parts = line.split_ascii_whitespace();
-            let _ = encoding_parts.next().unwrap(); //iterate over key name
-
-            for encoding in encoding_parts {
-                encodings.push(encoding.trim_end_matches(","));
-            }
-            break;
+    if fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+        match directory_index(stream, root, server, request, &path, keep_alive).await? {
+            Some(index) => path = index,
+            None => return Ok(()),
         }
     }
+    let content_type = mime_types.content_type(&path);
+    // precompressed files win over the cache and dynamic compression, ranges use the identity file
+    if (method.eq_ignore_ascii_case("get") || head_only)
+        && request.headers.get("range").is_none()
+        && let Some(sidecar) = server.precompressed.find(&path, &accept_encoding).await
+    {
+        return send_sidecar(
+            stream,
+            server,
+            request,
+            &path,
+            &content_type,
+            sidecar,
+            keep_alive,
+        )
+        .await;
+    }
+    let get_or_head = method.eq_ignore_ascii_case("get") || head_only;
+    // compressed responses are sent chunked, which HTTP/1.0 clients don't understand.
+    // HEAD negotiates like GET, so its headers are those of the body a GET would get.
+    // gzip_types and gzip_min_length only apply while the client also takes the identity
+    let identity_acceptable = accept_encoding.identity_acceptable();
+    let preferred = match request.version != Version::Http10
+        && (server.compression.compresses_type(&content_type) || !identity_acceptable)
+    {
+        true => server.compression.negotiate(&accept_encoding),
+        false => None,
+    };
+    // entries checked within open_file_cache_valid are served without looking at the file,
+    // a compressed one only exists when the file was large enough to compress
+    let preferred_key = CacheKey {
+        path: path.clone(),
+        encoding: preferred.unwrap_or(Encoding::None),
+    };
+    if get_or_head
+        && (preferred.is_some() || identity_acceptable)
+        && let Some(entry) = cache.get(&preferred_key)
+        && entry.is_fresh(server.cache_valid)
+    {
+        println!("Cached Ok");
+        return send_cached(
+            stream,
+            server,
+            request,
+            &content_type,
+            &preferred_key,
+            &entry,
+            keep_alive,
+        )
+        .await;
+    }
 
-    println!("Method {}, Path {}", method, requested_path);
-    println!("Encodings supported {:?}", encodings);
-    //checking cached response
-
-    if let Some(path) = safe_path(root, requested_path) {
-        if method.to_lowercase().eq("get")
-            && let Some(data) = cache.get(&path).await
-        {
-            stream
-                .write_all(create_response(&data, &path).as_bytes())
-                .await
-                .unwrap();
-            // Send file contents
-            stream.write_all(&data).await.unwrap();
-            stream.flush().await.unwrap();
+    let metadata = fs::metadata(&path).await.ok();
+    let encoding = preferred.filter(|_| {
+        !identity_acceptable
+            || metadata.as_ref().is_some_and(|metadata| {
+                server.compression.compresses(&content_type, metadata.len())
+            })
+    });
+    if encoding.is_none() && !identity_acceptable && metadata.is_some() {
+        return send_error(stream, server, request, Status::NotAcceptable, keep_alive).await;
+    }
+    let cache_key = CacheKey {
+        path: path.clone(),
+        encoding: encoding.unwrap_or(Encoding::None),
+    };
+    // entries made from an older version of the file are stale, and replaced below
+    if get_or_head
+        && let Some(metadata) = &metadata
+        && let Some(entry) = cache.get(&cache_key)
+        && entry.modified == metadata.modified().ok()
+    {
+        send_cached(
+            stream,
+            server,
+            request,
+            &content_type,
+            &cache_key,
+            &entry,
+            keep_alive,
+        )
+        .await?;
+        entry.revalidated();
+        return Ok(());
+    }
+    let file_result = fs::File::open(&path).await;
+    if let Ok(file) = file_result {
+        let mut file = file;
+        let metadata = file.metadata().await?;
+        let file_size = metadata.len();
+        println!("file size: {}", file_size);
 
-            println!("Cached Ok");
+        let validators = Validators::new(&metadata);
+        let precondition = evaluate(request, &validators);
+        let headers = file_headers(server, Some(&validators), encoding.is_some());
+        if send_precondition(stream, precondition, &headers, keep_alive).await? {
             return Ok(());
         }
-        let file_result = fs::File::open(&path).await;
-        if let Ok(file) = file_result {
-            let mut file = file;
-            let metadata = file.metadata().await.unwrap();
-            let file_size = metadata.len();
-            println!("file size: {}", file_size);
-
-            //compressed
-            for encoding in encodings {
-                if encoding == GZIP {
-                    write_header(stream, &metadata, &path, Encoding::GZIP).await;
-                    // let buf = Vec::new();
-                    let _ = compress_stream(&mut file, stream).await;
-
-                    return Ok(());
-                }
+
+        // ranges are always served from the identity representation
+        let range = match identity_acceptable {
+            true => requested_range(request, file_size, Some(&validators)),
+            false => RangeRequest::Full,
+        };
+        match range {
+            RangeRequest::Unsatisfiable => {
+                return send_unsatisfiable(stream, file_size, keep_alive).await;
+            }
+            RangeRequest::Partial(ranges) => {
+                // only the requested bytes are read, cached entries are served from memory above
+                let extra_headers = file_headers(server, Some(&validators), false);
+                let source = RangeSource::File(&mut file);
+                return send_ranges(
+                    stream,
+                    &ranges,
+                    file_size,
+                    &content_type,
+                    &extra_headers,
+                    keep_alive,
+                    source,
+                )
+                .await;
             }
+            RangeRequest::Full => {}
+        }
 
-            //uncompressed
-            if file_size < 1024 * 1024 * 100 {
-                handle_unchuncked_file(&mut file, &metadata, cache, stream, &path).await;
-            } else {
-                handle_chunked_file(&mut file, &metadata, cache, stream, &path).await;
+        //compressed
+        if let Some(encoding) = encoding {
+            let level = server.compression.level(encoding);
+            // files the cache can take are compressed once, then sent with a length
+            if cache.fits(file_size) {
+                let mut contents = Vec::new();
+                file.read_to_end(&mut contents).await?;
+                let compressed = compress(&contents, encoding, level).await?;
+                let entry = cache_entry(server, &content_type, &metadata, encoding, compressed);
+                send_entry(stream, &entry, head_only, keep_alive).await?;
+                // compressed only because identity was refused, other clients get the file as is
+                if server.compression.compresses(&content_type, file_size) {
+                    cache.insert(cache_key, entry);
+                }
+                return Ok(());
+            }
+            write_header(stream, &metadata, server, &path, encoding, keep_alive).await?;
+            if !head_only {
+                compress_stream(&mut file, &mut *stream, encoding, level).await?;
             }
+            stream.flush().await?;
 
             return Ok(());
+        }
+
+        //uncompressed
+        if head_only {
+            write_header(stream, &metadata, server, &path, Encoding::None, keep_alive).await?;
+            stream.flush().await?;
+        } else if file_size < 1024 * 1024 * 100 {
+            handle_unchuncked_file(&mut file, &metadata, server, stream, &cache_key, keep_alive)
+                .await?;
         } else {
-            // Send 404 response before closing
+            handle_chunked_file(&mut file, &metadata, server, stream, &path, keep_alive).await?;
+        }
+
+        Ok(())
+    } else {
+        eprintln!("File not found: {:?}", path);
+        send_error(stream, server, request, Status::NotFound, keep_alive).await
+    }
+}
 
-            let _ = stream.write_all(NOT_FOUND_RESPONSE).await;
-            let _ = stream.flush().await;
-            let _ = stream.shutdown().await;
+/// Serves a precompressed sibling file with the validators of the original
+async fn send_sidecar<S: ClientStream>(
+    stream: &mut S,
+    server: &Server,
+    request: &Request,
+    path: &Path,
+    content_type: &str,
+    sidecar: Sidecar,
+    keep_alive: bool,
+) -> Result<(), Error> {
+    let validators = Validators::new(&fs::metadata(path).await?);
+    let precondition = evaluate(request, &validators);
+    let headers = file_headers(server, Some(&validators), true);
+    if send_precondition(stream, precondition, &headers, keep_alive).await? {
+        return Ok(());
+    }
 
-            return Err(Error::other("File not found"));
+    let length = sidecar.metadata.len();
+    Response::new(Status::Ok)
+        .content_length(length)
+        .header("Content-Type", content_type)
+        .header("Content-Encoding", sidecar.encoding)
+        .headers(&headers)
+        .keep_alive(keep_alive)
+        .write_head(stream)
+        .await?;
+    if !request.method.eq_ignore_ascii_case("head") {
+        let file = File::open(&sidecar.path).await?;
+        if copy(&mut file.take(length), stream).await? < length {
+            return Err(Error::other("Precompressed file shrank while being sent"));
         }
     }
+    stream.flush().await
+}
 
-    // Invalid path - send 400 and close
+/// Headers of a full response in one encoding, compressed ones get weak validators
+fn representation_headers(
+    server: &Server,
+    content_type: &str,
+    validators: &Validators,
+    encoding: Encoding,
+) -> Headers {
+    let mut headers = Headers::new();
+    headers.append("Content-Type", content_type);
+    match encoding {
+        Encoding::None => {
+            for (name, value) in file_headers(server, Some(validators), false).iter() {
+                headers.append(name, value);
+            }
+            headers.append("Accept-Ranges", "bytes");
+        }
+        _ => {
+            headers.append("Content-Encoding", encoding.name());
+            for (name, value) in file_headers(server, Some(validators), true).iter() {
+                headers.append(name, value);
+            }
+        }
+    }
+    headers
+}
 
-    let _ = stream.write_all(BAD_REQUEST_RESPONSE).await;
-    let _ = stream.flush().await;
-    let _ = stream.shutdown().await;
+/// Validators and configured headers sent with every representation of a file
+fn file_headers(server: &Server, validators: Option<&Validators>, weak: bool) -> Headers {
+    let mut headers = validators
+        .map(|validators| validators.headers(weak))
+        .unwrap_or_default();
+    for (name, value) in server.mime_types.extra_headers().iter() {
+        headers.append(name, value);
+    }
+    if server.compression.is_enabled() || server.precompressed.is_enabled() {
+        headers.append("Vary", "Accept-Encoding");
+    }
+    headers
+}
 
-    Err(Error::other("Invalid path requested"))
+/// First `try_files` candidate that exists under `root`, directories only match entries ending in `/`
+pub async fn try_files(root: &Path, try_files: &TryFiles, uri: &str) -> Option<String> {
+    for candidate in &try_files.candidates {
+        let candidate = expand(candidate, uri);
+        let Ok(path) = safe_path(root, &candidate) else {
+            continue;
+        };
+        let Ok(metadata) = fs::metadata(&path).await else {
+            continue;
+        };
+        if metadata.is_dir() == candidate.ends_with('/') {
+            return Some(candidate);
+        }
+    }
+    None
 }
 
-fn safe_path(root: &Path, requested_path: &str) -> Option<PathBuf> {
+/// Resolves a directory request to its index file, otherwise answers it with
+/// a redirect, a listing or 403 and returns None
+async fn directory_index<S: ClientStream>(
+    stream: &mut S,
+    root: &Path,
+    server: &Server,
+    request: &Request,
+    directory: &Path,
+    keep_alive: bool,
+) -> Result<Option<PathBuf>, Error> {
+    let requested_path = request.uri.as_str();
+    if !requested_path.ends_with('/') {
+        let location = match request.query() {
+            Some(query) => format!("{}/?{}", percent_encode(requested_path), query),
+            None => format!("{}/", percent_encode(requested_path)),
+        };
+        Response::new(Status::MovedPermanently)
+            .header("Location", &location)
+            .empty()
+            .keep_alive(keep_alive)
+            .send(stream, true)
+            .await?;
+        return Ok(None);
+    }
+
+    for index in &server.index {
+        if let Ok(path) = safe_path(root, &format!("{}{}", requested_path, index))
+            && fs::metadata(&path).await.is_ok_and(|m| m.is_file())
+        {
+            return Ok(Some(path));
+        }
+    }
+
+    match &server.autoindex {
+        Some(autoindex) => {
+            autoindex
+                .send(stream, request, directory, keep_alive)
+                .await?
+        }
+        None => {
+            eprintln!("Directory index forbidden: {:?}", directory);
+            send_error(stream, server, request, Status::Forbidden, keep_alive).await?
+        }
+    }
+    Ok(None)
+}
+
+/// File for a request path under `root`, or the status to answer with when there is none
+pub fn safe_path(root: &Path, requested_path: &str) -> Result<PathBuf, Status> {
     let requested_path = requested_path.trim_start_matches(['/', '\\']);
     let path = root.join(requested_path);
     // println!("root {:?},requested {}, pathbuf {:?}", root, requested_path, path);
-    if let (Ok(path), Ok(canon_root)) = (path.canonicalize(), root.canonicalize()) {
-        if path.starts_with(&canon_root) {
-            return Some(path);
-        } else {
-            eprintln!(
-                "Reqested Path {:?} doesn't start with root {:?}",
-                requested_path, canon_root
-            );
-        }
+    let canon_root = root.canonicalize().map_err(|_| Status::NotFound)?;
+    let path = path.canonicalize().map_err(|e| match e.kind() {
+        ErrorKind::PermissionDenied => Status::Forbidden,
+        _ => Status::NotFound,
+    })?;
+    if path.starts_with(&canon_root) {
+        return Ok(path);
     }
-
-    None
+    eprintln!(
+        "Reqested Path {:?} doesn't start with root {:?}",
+        requested_path, canon_root
+    );
+    Err(Status::BadRequest)
 }
 
-async fn handle_unchuncked_file(
+async fn handle_unchuncked_file<S: ClientStream>(
     file: &mut File,
     metadata: &Metadata,
-    cache: &Arc<Cache>,
-    stream: &mut TcpStream,
-    path: &PathBuf,
-) {
+    server: &Server,
+    stream: &mut S,
+    key: &CacheKey,
+    keep_alive: bool,
+) -> Result<(), Error> {
     let mut contents = Vec::new();
-    let _ = file.read_to_end(&mut contents).await.unwrap();
-    cache.add(path, &contents).await;
+    file.read_to_end(&mut contents).await?;
+    let content_type = server.mime_types.content_type(&key.path);
+    let entry = cache_entry(server, &content_type, metadata, Encoding::None, contents);
 
-    write_header(stream, metadata, path, Encoding::NONE).await;
+    send_entry(stream, &entry, false, keep_alive).await?;
+    server.cache.insert(key.clone(), entry);
+    Ok(())
+}
 
+/// Answers from a cache entry, identity entries also serve ranges
+async fn send_cached<S: ClientStream>(
+    stream: &mut S,
+    server: &Server,
+    request: &Request,
+    content_type: &str,
+    key: &CacheKey,
+    entry: &CacheEntry,
+    keep_alive: bool,
+) -> Result<(), Error> {
+    let validators = &entry.validators;
+    let precondition = evaluate(request, validators);
+    let headers = file_headers(server, Some(validators), key.encoding != Encoding::None);
+    if send_precondition(stream, precondition, &headers, keep_alive).await? {
+        return Ok(());
+    }
+    let size = entry.data.len() as u64;
+    let range = match key.encoding {
+        Encoding::None => requested_range(request, size, Some(validators)),
+        _ => RangeRequest::Full,
+    };
+    match range {
+        RangeRequest::Unsatisfiable => send_unsatisfiable(stream, size, keep_alive).await,
+        RangeRequest::Partial(ranges) => {
+            let extra_headers = file_headers(server, Some(validators), false);
+            let source = RangeSource::Memory(&entry.data);
+            send_ranges(
+                stream,
+                &ranges,
+                size,
+                content_type,
+                &extra_headers,
+                keep_alive,
+                source,
+            )
+            .await
+        }
+        RangeRequest::Full => {
+            let head_only = request.method.eq_ignore_ascii_case("head");
+            send_entry(stream, entry, head_only, keep_alive).await
+        }
+    }
+}
+
+/// A full response for a file, kept with the file's mtime so changes can be noticed
+fn cache_entry(
+    server: &Server,
+    content_type: &str,
+    metadata: &Metadata,
+    encoding: Encoding,
+    data: Vec<u8>,
+) -> CacheEntry {
+    let validators = Validators::new(metadata);
+    CacheEntry {
+        data: data.into(),
+        headers: representation_headers(server, content_type, &validators, encoding),
+        validators,
+        modified: metadata.modified().ok(),
+        validated: Mutex::new(Instant::now()),
+    }
+}
+
+async fn send_entry<S: ClientStream>(
+    stream: &mut S,
+    entry: &CacheEntry,
+    head_only: bool,
+    keep_alive: bool,
+) -> Result<(), Error> {
+    Response::new(Status::Ok)
+        .content_length(entry.data.len() as u64)
+        .headers(&entry.headers)
+        .keep_alive(keep_alive)
+        .write_head(stream)
+        .await?;
     // Send file contents
-    stream.write_all(&contents).await.unwrap();
-    stream.flush().await.unwrap();
+    if !head_only {
+        stream.write_all(&entry.data).await?;
+    }
+    stream.flush().await
 }
 
-async fn handle_chunked_file(
+async fn handle_chunked_file<S: ClientStream>(
     file: &mut File,
     metadata: &Metadata,
-    cache: &Arc<Cache>,
-    stream: &mut TcpStream,
+    server: &Server,
+    stream: &mut S,
     path: &Path,
-) {
+    keep_alive: bool,
+) -> Result<(), Error> {
     const BUFFER_SIZE: usize = 1024 * 16; //16KB
     let mut buffer: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE];
-    write_header(stream, metadata, path, Encoding::NONE).await;
+    write_header(stream, metadata, server, path, Encoding::None, keep_alive).await?;
     loop {
-        let bytes_read = file.read(&mut buffer).await;
-        if let Ok(n) = bytes_read {
-            if n == 0 {
-                stream.flush().await.unwrap();
-                return;
-            } else {
-                let _ = stream.write_all(&buffer[..n]).await;
-            }
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            return stream.flush().await;
         }
+        stream.write_all(&buffer[..n]).await?;
     }
 }
 
-async fn write_header(
-    stream: &mut TcpStream,
+async fn write_header<S: ClientStream>(
+    stream: &mut S,
     metadata: &Metadata,
+    server: &Server,
     path: &Path,
     encoding: Encoding,
-) {
+    keep_alive: bool,
+) -> Result<(), Error> {
     let file_size = metadata.len();
-    let file_type = get_file_type(path);
-    let parsed_encoding = match encoding {
-        Encoding::GZIP => "gzip",
-        Encoding::NONE => "",
-    };
+    let file_type = server.mime_types.content_type(path);
+    let validators = Validators::new(metadata);
+    let headers = representation_headers(server, &file_type, &validators, encoding);
 
-    if parsed_encoding.is_empty() {
-        let response = format!(
-            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nContent-Type: {}\r\n\r\n",
-            file_size, file_type
-        );
-        println!("Response: {}", response);
-        stream.write_all(response.as_bytes()).await.unwrap();
-    } else {
-        let response = format!(
-            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Encoding: {}\r\nTransfer-Encoding: chunked\r\n\r\n",
-            file_type, parsed_encoding
-        );
-        println!("Response: {}", response);
-        stream.write_all(response.as_bytes()).await.unwrap();
+    let response = match encoding {
+        Encoding::None => Response::new(Status::Ok)
+            .content_length(file_size)
+            .headers(&headers),
+        _ => Response::new(Status::Ok)
+            .headers(&headers)
+            .header("Transfer-Encoding", "chunked"),
+    }
+    .keep_alive(keep_alive);
+    println!("Response: {}", response.head());
+    response.write_head(stream).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::ServerConfig, http_parser::request::parse_request};
+    use tokio::io::duplex;
+
+    async fn respond(server: &Server, root: &Path, head: &str) -> String {
+        let request = parse_request(head.as_bytes()).unwrap();
+        let (mut stream, mut client) = duplex(64 * 1024);
+        handle_static_files(&mut stream, root, server, &request, false)
+            .await
+            .unwrap();
+        drop(stream);
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_head_refusing_identity() {
+        let root = std::env::temp_dir().join(format!("rs-ngnix-static-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "text ".repeat(1000)).unwrap();
+        let head = "HEAD /a.txt HTTP/1.1\r\nHost: a\r\nAccept-Encoding: gzip, identity;q=0\r\n\r\n";
+        let get = head.replacen("HEAD", "GET", 1);
+
+        // compressed once and cached, a GET then gets the body the HEAD described
+        for cache in [0, 1024] {
+            let config: ServerConfig =
+                serde_yaml::from_str(&format!("listen: 0\ncache: {}", cache)).unwrap();
+            let server = Server::new(&config).unwrap();
+            let head_response = respond(&server, &root, head).await;
+            assert!(head_response.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(head_response.contains("Content-Encoding: gzip\r\n"));
+            assert!(head_response.ends_with("\r\n\r\n"));
+            let get_response = respond(&server, &root, &get).await;
+            let (get_head, _) = get_response.split_once("\r\n\r\n").unwrap();
+            assert_eq!(format!("{}\r\n\r\n", get_head), head_response);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_small_file_refusing_identity() {
+        let root = std::env::temp_dir().join(format!("rs-ngnix-small-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "text").unwrap();
+        std::fs::write(root.join("a.bin"), "data").unwrap();
+        let config: ServerConfig =
+            serde_yaml::from_str("listen: 0\ncache: 1024\ngzip_min_length: 1024").unwrap();
+        let server = Server::new(&config).unwrap();
+
+        // below gzip_min_length or outside gzip_types, still compressed when identity is refused
+        for path in ["/a.txt", "/a.bin"] {
+            let refusing = format!(
+                "GET {} HTTP/1.1\r\nHost: a\r\nAccept-Encoding: gzip, identity;q=0\r\n\r\n",
+                path
+            );
+            let response = respond(&server, &root, &refusing).await;
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(response.contains("Content-Encoding: gzip\r\n"));
+
+            let accepting = refusing.replace(", identity;q=0", "");
+            let response = respond(&server, &root, &accepting).await;
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(!response.contains("Content-Encoding"));
+        }
+
+        let nothing = "GET /a.txt HTTP/1.1\r\nHost: a\r\nAccept-Encoding: identity;q=0\r\n\r\n";
+        let response = respond(&server, &root, nothing).await;
+        assert!(response.starts_with("HTTP/1.1 406"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_compressed() {
+        let root = std::env::temp_dir().join(format!("rs-ngnix-304-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "text ".repeat(1000)).unwrap();
+        let get = "GET /a.txt HTTP/1.1\r\nHost: a\r\nAccept-Encoding: gzip\r\n";
+
+        // from the file, then from the cache entry the first request made
+        for cache in [0, 1024] {
+            let config: ServerConfig =
+                serde_yaml::from_str(&format!("listen: 0\ncache: {}", cache)).unwrap();
+            let server = Server::new(&config).unwrap();
+            let response = respond(&server, &root, &format!("{}\r\n", get)).await;
+            let etag = response
+                .lines()
+                .find_map(|line| line.strip_prefix("ETag: "))
+                .unwrap();
+            assert!(etag.starts_with("W/"));
+            for _ in 0..2 {
+                let conditional = format!("{}If-None-Match: {}\r\n\r\n", get, etag);
+                let response = respond(&server, &root, &conditional).await;
+                assert!(response.starts_with("HTTP/1.1 304"));
+                assert!(response.contains(&format!("ETag: {}\r\n", etag)));
+                assert!(response.contains("Vary: Accept-Encoding\r\n"));
+            }
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_range_from_file() {
+        let root = std::env::temp_dir().join(format!("rs-ngnix-range-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.bin"), b"0123456789").unwrap();
+        let config: ServerConfig = serde_yaml::from_str("listen: 0\ncache: 1024").unwrap();
+        let server = Server::new(&config).unwrap();
+
+        let range = "GET /a.bin HTTP/1.1\r\nHost: a\r\nRange: bytes=2-4\r\n\r\n";
+        let response = respond(&server, &root, range).await;
+        assert!(response.starts_with("HTTP/1.1 206"));
+        assert!(response.contains("Content-Range: bytes 2-4/10\r\n"));
+        assert!(response.ends_with("\r\n\r\n234"));
+        // the file was seeked in, not read into the cache like for a full response
+        let key = CacheKey {
+            path: root.join("a.bin").canonicalize().unwrap(),
+            encoding: Encoding::None,
+        };
+        assert!(server.cache.get(&key).is_none());
+        respond(&server, &root, &range.replace("Range", "X-Range")).await;
+        assert!(server.cache.get(&key).is_some());
+
+        std::fs::remove_dir_all(&root).unwrap();
     }
 }
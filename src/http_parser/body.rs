@@ -0,0 +1,63 @@
+use std::io::{Error, ErrorKind};
+
+use crate::http_parser::{request::Request, response::ResponseHead};
+
+/// How the end of a message body is found (RFC 9112 section 6.3)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Body {
+    Empty,
+    Length(u64),
+    Chunked,
+    UntilClose,
+}
+
+pub fn request_body(request: &Request) -> Body {
+    if request.headers.get("transfer-encoding").is_some() {
+        // the parser only lets through requests with chunked as the final coding
+        Body::Chunked
+    } else {
+        match request.content_length() {
+            Some(0) | None => Body::Empty,
+            Some(length) => Body::Length(length),
+        }
+    }
+}
+
+pub fn response_body(request_method: &str, response: &ResponseHead) -> Body {
+    if request_method.eq_ignore_ascii_case("head")
+        || response.status / 100 == 1
+        || response.status == 204
+        || response.status == 304
+    {
+        return Body::Empty;
+    }
+    if let Some(encoding) = response.headers.get("transfer-encoding") {
+        if last_coding_is_chunked(encoding) {
+            return Body::Chunked;
+        }
+        return Body::UntilClose;
+    }
+    match response.content_length() {
+        Some(length) => Body::Length(length),
+        None => Body::UntilClose,
+    }
+}
+
+pub fn last_coding_is_chunked(transfer_encoding: &str) -> bool {
+    transfer_encoding
+        .rsplit(',')
+        .next()
+        .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+}
+
+/// Parses a chunk size line such as `1a;name=value\r\n`
+pub fn parse_chunk_size(line: &[u8]) -> Result<u64, Error> {
+    let line = String::from_utf8_lossy(line);
+    let size = line
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim_matches([' ', '\t', '\r', '\n']);
+    u64::from_str_radix(size, 16)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid chunk size"))
+}
@@ -0,0 +1,6 @@
+pub mod body;
+pub mod headers;
+pub mod reader;
+pub mod request;
+pub mod response;
+pub mod uri;
@@ -0,0 +1,271 @@
+use std::io::{Error, ErrorKind};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, copy};
+
+use crate::http_parser::{
+    body::{Body, parse_chunk_size},
+    request::{ParseError, Request, parse_request},
+    response::{ResponseHead, parse_response},
+};
+
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 8 * 1024; // in b
+
+/// Incrementally reads messages from a stream.
+/// Bytes received past the end of a message stay buffered for the next read.
+pub struct HttpReader {
+    buf: Vec<u8>,
+    max_header_size: usize,
+}
+
+impl HttpReader {
+    pub fn new(max_header_size: usize) -> HttpReader {
+        HttpReader {
+            buf: Vec::with_capacity(1024),
+            max_header_size,
+        }
+    }
+
+    /// Whether no bytes past the last message are buffered
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Returns `Ok(None)` when the peer closed the connection before sending anything
+    pub async fn read_request<R>(&mut self, reader: &mut R) -> Result<Option<Request>, ParseError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        match self.read_head(reader).await? {
+            Some(head) => parse_request(&head).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `Ok(None)` when the upstream closed the connection before answering
+    pub async fn read_response<R>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<Option<ResponseHead>, ParseError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        match self.read_head(reader).await? {
+            Some(head) => parse_response(&head).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn read_head<R>(&mut self, reader: &mut R) -> Result<Option<Vec<u8>>, ParseError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        loop {
+            // empty lines before the start line are ignored (RFC 9112 section 2.2)
+            let leading = self
+                .buf
+                .iter()
+                .take_while(|b| **b == b'\r' || **b == b'\n')
+                .count();
+            self.buf.drain(..leading);
+
+            if let Some(end) = find_head_end(&self.buf) {
+                if end > self.max_header_size {
+                    return Err(ParseError::HeadersTooLarge);
+                }
+                return Ok(Some(self.buf.drain(..end).collect()));
+            }
+            if self.buf.len() > self.max_header_size {
+                return Err(ParseError::HeadersTooLarge);
+            }
+
+            let n = reader.read_buf(&mut self.buf).await?;
+            if n == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                return Err(ParseError::Malformed("connection closed mid message"));
+            }
+        }
+    }
+
+    /// Copies a message body from `reader` to `writer`, starting with what is already buffered.
+    /// Chunked framing is passed through as is unless `dechunk` is set.
+    pub async fn copy_body<R, W>(
+        &mut self,
+        reader: &mut R,
+        writer: &mut W,
+        body: Body,
+        dechunk: bool,
+    ) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        match body {
+            Body::Empty => Ok(()),
+            Body::Length(length) => self.copy_exact(reader, writer, length).await,
+            Body::Chunked => loop {
+                let size_line = self.read_line(reader).await?;
+                let size = parse_chunk_size(&size_line)?;
+                if !dechunk {
+                    writer.write_all(&size_line).await?;
+                }
+
+                if size == 0 {
+                    // trailer section ends with an empty line
+                    loop {
+                        let line = self.read_line(reader).await?;
+                        if !dechunk {
+                            writer.write_all(&line).await?;
+                        }
+                        if line == b"\r\n" || line == b"\n" {
+                            return Ok(());
+                        }
+                    }
+                }
+
+                self.copy_exact(reader, writer, size).await?;
+                let line_end = self.read_line(reader).await?;
+                if line_end != b"\r\n" && line_end != b"\n" {
+                    return Err(Error::new(ErrorKind::InvalidData, "Invalid chunk ending"));
+                }
+                if !dechunk {
+                    writer.write_all(&line_end).await?;
+                }
+            },
+            Body::UntilClose => {
+                writer.write_all(&self.buf).await?;
+                self.buf.clear();
+                copy(reader, writer).await?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn copy_exact<R, W>(
+        &mut self,
+        reader: &mut R,
+        writer: &mut W,
+        length: u64,
+    ) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let buffered = self
+            .buf
+            .len()
+            .min(usize::try_from(length).unwrap_or(usize::MAX));
+        writer.write_all(&self.buf[..buffered]).await?;
+        self.buf.drain(..buffered);
+
+        let remaining = length - buffered as u64;
+        let copied = copy(&mut (&mut *reader).take(remaining), writer).await?;
+        if copied < remaining {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Message body cut short",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads one line, line ending included
+    async fn read_line<R>(&mut self, reader: &mut R) -> Result<Vec<u8>, Error>
+    where
+        R: AsyncRead + Unpin,
+    {
+        loop {
+            if let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+                return Ok(self.buf.drain(..=end).collect());
+            }
+            if self.buf.len() > self.max_header_size {
+                return Err(Error::new(ErrorKind::InvalidData, "Line too long"));
+            }
+            if reader.read_buf(&mut self.buf).await? == 0 {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Message body cut short",
+                ));
+            }
+        }
+    }
+}
+
+/// Position right after the empty line ending the head, accepting bare LF line endings
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.iter().enumerate().find_map(|(i, b)| {
+        if *b != b'\n' {
+            return None;
+        }
+        match &buf[i + 1..] {
+            [b'\n', ..] => Some(i + 2),
+            [b'\r', b'\n', ..] => Some(i + 3),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_split_requests() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let writer = tokio::spawn(async move {
+            for part in [
+                &b"GET /a HTTP/1.1\r\nHo"[..],
+                b"st: x\r\n\r\nGET /b HTTP/1.0\r\n",
+                b"\r\n",
+            ] {
+                client.write_all(part).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let mut reader = HttpReader::new(DEFAULT_MAX_HEADER_SIZE);
+        let first = reader.read_request(&mut server).await.unwrap().unwrap();
+        assert_eq!(first.target, "/a");
+        let second = reader.read_request(&mut server).await.unwrap().unwrap();
+        assert_eq!(second.target, "/b");
+        writer.await.unwrap();
+        assert!(reader.read_request(&mut server).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_headers_too_large() {
+        let mut input = b"GET / HTTP/1.1\r\nHost: x\r\nX-Big: ".to_vec();
+        input.extend(vec![b'a'; 200]);
+        input.extend(b"\r\n\r\n");
+
+        let mut reader = HttpReader::new(128);
+        let result = reader.read_request(&mut input.as_slice()).await;
+        assert!(matches!(result, Err(ParseError::HeadersTooLarge)));
+    }
+
+    #[tokio::test]
+    async fn test_copy_chunked_body() {
+        let input: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\nnext";
+
+        for (dechunk, expected) in [
+            (
+                false,
+                &b"5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\n"[..],
+            ),
+            (true, b"hello world"),
+        ] {
+            let mut input = input;
+            let mut reader = HttpReader::new(DEFAULT_MAX_HEADER_SIZE);
+            reader.read_response(&mut input).await.unwrap().unwrap();
+
+            let mut output = Vec::new();
+            reader
+                .copy_body(&mut input, &mut output, Body::Chunked, dechunk)
+                .await
+                .unwrap();
+            assert_eq!(output, expected);
+            assert_eq!(reader.buf, b"next");
+        }
+    }
+}
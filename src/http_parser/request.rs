@@ -0,0 +1,265 @@
+use std::{fmt, io::Error};
+
+use crate::{
+    http_parser::{
+        body::last_coding_is_chunked,
+        headers::Headers,
+        uri::{percent_encode, resolve},
+    },
+    response_builder::status::Status,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub uri: String, // path decoded and resolved when routing, nginx `$uri`
+    pub version: Version,
+    pub headers: Headers,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Malformed(&'static str),
+    HeadersTooLarge,
+    Io(Error),
+}
+
+impl Request {
+    /// Request target without the query string
+    pub fn path(&self) -> &str {
+        match self.target.split_once('?') {
+            Some((path, _)) => path,
+            None => &self.target,
+        }
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.target.split_once('?').map(|(_, query)| query)
+    }
+
+    /// Changes the path for an internal redirect, keeping the query unless `uri` has its own.
+    /// `uri` is decoded like `self.uri`, the target gets it percent-encoded.
+    pub fn rewrite(&mut self, uri: &str) {
+        let (path, query) = match uri.split_once('?') {
+            Some((path, query)) => (path, Some(query.to_string())),
+            None => (uri, self.query().map(String::from)),
+        };
+        self.uri = resolve(path).unwrap_or_else(|| path.to_string());
+        self.target = match query {
+            Some(query) => format!("{}?{}", percent_encode(&self.uri), query),
+            None => percent_encode(&self.uri),
+        };
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+    }
+
+    /// Whether the client allows the connection to stay open after this request
+    pub fn keep_alive(&self) -> bool {
+        match self.version {
+            Version::Http11 => !self.headers.has_token("connection", "close"),
+            Version::Http10 => self.headers.has_token("connection", "keep-alive"),
+        }
+    }
+}
+
+impl ParseError {
+    /// Status to answer with before closing the connection, `None` when the client is gone
+    pub fn status(&self) -> Option<Status> {
+        match self {
+            ParseError::Malformed(_) => Some(Status::BadRequest),
+            ParseError::HeadersTooLarge => Some(Status::HeaderFieldsTooLarge),
+            ParseError::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Malformed(reason) => write!(f, "Malformed request: {}", reason),
+            ParseError::HeadersTooLarge => write!(f, "Request headers too large"),
+            ParseError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<Error> for ParseError {
+    fn from(e: Error) -> Self {
+        ParseError::Io(e)
+    }
+}
+
+/// Parses a complete request head (request line and headers, terminating empty line included)
+pub fn parse_request(head: &[u8]) -> Result<Request, ParseError> {
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line));
+
+    let request_line = lines
+        .next()
+        .ok_or(ParseError::Malformed("missing request line"))?;
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::Malformed("invalid request line"));
+    };
+
+    if method.is_empty() || !method.bytes().all(is_token_char) {
+        return Err(ParseError::Malformed("invalid method"));
+    }
+    if target.is_empty() || target.bytes().any(|b| b.is_ascii_control()) {
+        return Err(ParseError::Malformed("invalid request target"));
+    }
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        _ => return Err(ParseError::Malformed("unsupported HTTP version")),
+    };
+
+    let mut headers = Headers::new();
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            // obsolete line folding (RFC 9112 section 5.2)
+            return Err(ParseError::Malformed("folded header line"));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ParseError::Malformed("header without colon"))?;
+        if name.is_empty() || !name.bytes().all(is_token_char) {
+            return Err(ParseError::Malformed("invalid header name"));
+        }
+        headers.append(name, value.trim_matches([' ', '\t']));
+    }
+
+    if version == Version::Http11 && headers.count("host") != 1 {
+        return Err(ParseError::Malformed(
+            "HTTP/1.1 request needs exactly one Host header",
+        ));
+    }
+    if headers.get("transfer-encoding").is_some() && headers.get("content-length").is_some() {
+        return Err(ParseError::Malformed(
+            "both Transfer-Encoding and Content-Length present",
+        ));
+    }
+    if let Some(encoding) = headers.get("transfer-encoding")
+        && !last_coding_is_chunked(encoding)
+    {
+        return Err(ParseError::Malformed(
+            "request body length can't be determined",
+        ));
+    }
+    // repeated or listed values have to agree, or the proxy and the upstream
+    // could frame the body differently (RFC 9112 section 6.3)
+    let lengths: Vec<String> = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|length| length.trim_matches([' ', '\t']).to_string())
+        .collect();
+    if let Some(length) = lengths.first() {
+        if length.is_empty() || !length.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::Malformed("invalid Content-Length"));
+        }
+        if lengths.iter().any(|other| other != length) {
+            return Err(ParseError::Malformed("conflicting Content-Length values"));
+        }
+        headers.set("Content-Length", length);
+    }
+
+    Ok(Request {
+        method: method.to_string(),
+        target: target.to_string(),
+        uri: target.split('?').next().unwrap_or(target).to_string(),
+        version,
+        headers,
+    })
+}
+
+fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request(
+            b"GET /index.html?v=1 HTTP/1.1\r\nHost: localhost\r\nACCEPT-ENCODING:  gzip, br \r\n\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.target, "/index.html?v=1");
+        assert_eq!(request.path(), "/index.html");
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.headers.get("accept-encoding"), Some("gzip, br"));
+        assert_eq!(request.headers.get("Host"), Some("localhost"));
+
+        let mut request = request;
+        request.rewrite("/a b/../c d.html");
+        assert_eq!(request.uri, "/c d.html");
+        assert_eq!(request.target, "/c%20d.html?v=1");
+        request.rewrite("/e?w=2");
+        assert_eq!(request.target, "/e?w=2");
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let keep_alive = |head: &[u8]| parse_request(head).unwrap().keep_alive();
+
+        assert!(keep_alive(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"));
+        assert!(!keep_alive(
+            b"GET / HTTP/1.1\r\nHost: a\r\nConnection: Close\r\n\r\n"
+        ));
+        assert!(!keep_alive(b"GET / HTTP/1.0\r\n\r\n"));
+        assert!(keep_alive(
+            b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn test_parse_malformed_request() {
+        let malformed: [&[u8]; 9] = [
+            b"GET /\r\n\r\n",
+            b"GET / HTTP/2.0\r\nHost: a\r\n\r\n",
+            b"GET / HTTP/1.1\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: a\r\nBad Header: x\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: a\r\nX-A: 1\r\n folded\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: -1\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 50\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5, 50\r\n\r\n",
+        ];
+        for head in malformed {
+            assert!(matches!(parse_request(head), Err(ParseError::Malformed(_))));
+        }
+
+        // identical repeats are collapsed, so only one is forwarded
+        for head in [
+            &b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\ncontent-length: 5\r\n\r\n"[..],
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5, 5\r\n\r\n",
+        ] {
+            let request = parse_request(head).unwrap();
+            assert_eq!(request.headers.count("content-length"), 1);
+            assert_eq!(request.content_length(), Some(5));
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::http_parser::{
+    headers::Headers,
+    request::{ParseError, Version},
+};
+
+/// Status line and headers of an upstream response
+#[derive(Debug)]
+pub struct ResponseHead {
+    pub version: Version,
+    pub status: u16,
+    pub reason: String,
+    pub headers: Headers,
+}
+
+impl ResponseHead {
+    pub fn content_length(&self) -> Option<u64> {
+        self.headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+    }
+
+    /// Whether the upstream allows the connection to stay open after this response
+    pub fn keep_alive(&self) -> bool {
+        match self.version {
+            Version::Http11 => !self.headers.has_token("connection", "close"),
+            Version::Http10 => self.headers.has_token("connection", "keep-alive"),
+        }
+    }
+}
+
+/// Parses a complete response head (status line and headers, terminating empty line included)
+pub fn parse_response(head: &[u8]) -> Result<ResponseHead, ParseError> {
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line));
+
+    let status_line = lines
+        .next()
+        .ok_or(ParseError::Malformed("missing status line"))?;
+    let mut parts = status_line.splitn(3, ' ');
+    let (Some(version), Some(status)) = (parts.next(), parts.next()) else {
+        return Err(ParseError::Malformed("invalid status line"));
+    };
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        _ => return Err(ParseError::Malformed("unsupported HTTP version")),
+    };
+    let status = match status.parse::<u16>() {
+        Ok(status) if status.to_string().len() == 3 => status,
+        _ => return Err(ParseError::Malformed("invalid status code")),
+    };
+
+    let mut headers = Headers::new();
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ParseError::Malformed("header without colon"))?;
+        headers.append(name.trim(), value.trim_matches([' ', '\t']));
+    }
+
+    Ok(ResponseHead {
+        version,
+        status,
+        reason: parts.next().unwrap_or("").to_string(),
+        headers,
+    })
+}
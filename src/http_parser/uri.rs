@@ -0,0 +1,105 @@
+/// Bytes sent as is in a request target path, everything else is percent-encoded (RFC 3986 section 3.3)
+const PATH_CHARS: &[u8] = b"-._~!$&'()*+,;=:@/";
+
+/// Decoded request path with repeated slashes merged and `.` and `..` resolved, like nginx `$uri`.
+/// `None` when it doesn't decode to UTF-8, has a NUL, or climbs above `/`.
+pub fn normalize(path: &str) -> Option<String> {
+    resolve(&percent_decode(path)?)
+}
+
+/// Merges repeated slashes and resolves `.` and `..` segments of an already decoded path
+pub fn resolve(path: &str) -> Option<String> {
+    if !path.starts_with('/') {
+        return None;
+    }
+    let mut segments: Vec<&str> = Vec::new();
+    let mut directory = false;
+    for segment in path.split('/') {
+        directory = true;
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            _ => {
+                segments.push(segment);
+                directory = false;
+            }
+        }
+    }
+    let mut resolved = format!("/{}", segments.join("/"));
+    if directory && !segments.is_empty() {
+        resolved.push('/');
+    }
+    Some(resolved)
+}
+
+/// Percent-encodes what can't appear in a request target path, the reverse of `percent_decode`
+pub fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || PATH_CHARS.contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Decodes `%XX` escapes, None for malformed escapes, NUL bytes or invalid UTF-8
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    if decoded.contains(&0) {
+        return None;
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("/").as_deref(), Some("/"));
+        assert_eq!(normalize("//admin/x").as_deref(), Some("/admin/x"));
+        assert_eq!(normalize("/%61dmin/x").as_deref(), Some("/admin/x"));
+        assert_eq!(normalize("/./admin/./x").as_deref(), Some("/admin/x"));
+        assert_eq!(normalize("/a/b/../c").as_deref(), Some("/a/c"));
+        assert_eq!(normalize("/a/b/..").as_deref(), Some("/a/"));
+        assert_eq!(normalize("/a/.").as_deref(), Some("/a/"));
+        assert_eq!(normalize("/a%20b/").as_deref(), Some("/a b/"));
+        assert_eq!(normalize("/a/%2e%2e/b").as_deref(), Some("/b"));
+        assert_eq!(normalize("/.."), None);
+        assert_eq!(normalize("/a/../../b"), None);
+        assert_eq!(normalize("/%2e%2e/etc"), None);
+        assert_eq!(normalize("/a%00"), None);
+        assert_eq!(normalize("/a%zz"), None);
+        assert_eq!(normalize("a/b"), None);
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(percent_encode("/a b/ü?#%"), "/a%20b/%C3%BC%3F%23%25");
+        assert_eq!(
+            normalize(&percent_encode("/a b/ü?#%")).as_deref(),
+            Some("/a b/ü?#%")
+        );
+    }
+}
@@ -1,2 +1,2 @@
 pub mod http;
-mod static_listener;
+pub mod tls;
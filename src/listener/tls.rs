@@ -0,0 +1,323 @@
+use std::{io::Error, sync::Arc};
+
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        self, SupportedProtocolVersion,
+        crypto::{CryptoProvider, ring},
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+        version::{TLS12, TLS13},
+    },
+};
+
+use crate::{
+    config::ServerConfig,
+    router::server_name::{ServerName, find_server},
+    server::default_server_index,
+};
+
+/// Only HTTP/1.x is spoken, so that is all ALPN offers
+pub const ALPN_PROTOCOLS: [&[u8]; 2] = [b"http/1.1", b"http/1.0"];
+
+/// Picks the certificate of the server block matching the SNI name, like `Host` for plain HTTP
+#[derive(Debug)]
+struct SniResolver {
+    names: Vec<Vec<ServerName>>,
+    keys: Vec<Option<Arc<CertifiedKey>>>,
+    default: Arc<CertifiedKey>,
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let key = client_hello
+            .server_name()
+            .and_then(|name| find_server(&self.names, name))
+            .and_then(|index| self.keys[index].clone());
+        Some(key.unwrap_or_else(|| self.default.clone()))
+    }
+}
+
+/// TLS acceptor for the servers of a port, `None` when none of them has a certificate.
+/// Protocol versions and cipher suites come from the default server.
+pub fn tls_acceptor(configs: &[ServerConfig]) -> Result<Option<TlsAcceptor>, Error> {
+    if configs
+        .iter()
+        .all(|config| config.ssl_certificate.is_none())
+    {
+        return Ok(None);
+    }
+    let default_config = &configs[default_server_index(configs)];
+    let provider = Arc::new(crypto_provider(default_config)?);
+
+    let keys = configs
+        .iter()
+        .map(|config| load_certified_key(config, &provider))
+        .collect::<Result<Vec<Option<Arc<CertifiedKey>>>, Error>>()?;
+    let default = keys[default_server_index(configs)]
+        .clone()
+        .or_else(|| keys.iter().flatten().next().cloned())
+        .ok_or_else(|| Error::other("No usable certificate"))?;
+    let names = configs
+        .iter()
+        .map(|config| {
+            config
+                .server_name
+                .iter()
+                .flatten()
+                .map(|name| ServerName::parse(name))
+                .collect::<Result<Vec<ServerName>, Error>>()
+        })
+        .collect::<Result<Vec<Vec<ServerName>>, Error>>()?;
+
+    let mut tls_config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&protocol_versions(default_config)?)
+        .map_err(|e| Error::other(format!("Invalid TLS settings: {}", e)))?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(SniResolver {
+            names,
+            keys,
+            default,
+        }));
+    tls_config.alpn_protocols = ALPN_PROTOCOLS
+        .iter()
+        .map(|protocol| protocol.to_vec())
+        .collect();
+
+    Ok(Some(TlsAcceptor::from(Arc::new(tls_config))))
+}
+
+fn load_certified_key(
+    config: &ServerConfig,
+    provider: &CryptoProvider,
+) -> Result<Option<Arc<CertifiedKey>>, Error> {
+    let (certificate_path, key_path) = match (&config.ssl_certificate, &config.ssl_certificate_key)
+    {
+        (Some(certificate_path), Some(key_path)) => (certificate_path, key_path),
+        (None, None) => return Ok(None),
+        _ => {
+            return Err(Error::other(
+                "Invalid Config: ssl_certificate and ssl_certificate_key go together",
+            ));
+        }
+    };
+
+    let certificates = CertificateDer::pem_file_iter(certificate_path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<CertificateDer>, _>>())
+        .map_err(|e| Error::other(format!("Invalid certificate {}: {}", certificate_path, e)))?;
+    if certificates.is_empty() {
+        return Err(Error::other(format!(
+            "No certificate found in {}",
+            certificate_path
+        )));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| Error::other(format!("Invalid private key {}: {}", key_path, e)))?;
+
+    CertifiedKey::from_der(certificates, key, provider)
+        .map(|key| Some(Arc::new(key)))
+        .map_err(|e| Error::other(format!("Invalid certificate {}: {}", certificate_path, e)))
+}
+
+/// `ssl_protocols` entries are `TLSv1.2` and `TLSv1.3`, both enabled by default
+fn protocol_versions(
+    config: &ServerConfig,
+) -> Result<Vec<&'static SupportedProtocolVersion>, Error> {
+    let Some(protocols) = &config.ssl_protocols else {
+        return Ok(vec![&TLS12, &TLS13]);
+    };
+    protocols
+        .iter()
+        .map(|protocol| match protocol.as_str() {
+            "TLSv1.2" => Ok(&TLS12),
+            "TLSv1.3" => Ok(&TLS13),
+            _ => Err(Error::other(format!(
+                "Unsupported TLS protocol: {}",
+                protocol
+            ))),
+        })
+        .collect()
+}
+
+/// `ssl_ciphers` entries use rustls names such as `TLS13_AES_256_GCM_SHA384`
+fn crypto_provider(config: &ServerConfig) -> Result<CryptoProvider, Error> {
+    let mut provider = ring::default_provider();
+    if let Some(ciphers) = &config.ssl_ciphers {
+        for cipher in ciphers {
+            if !provider
+                .cipher_suites
+                .iter()
+                .any(|suite| format!("{:?}", suite.suite()).eq_ignore_ascii_case(cipher))
+            {
+                return Err(Error::other(format!(
+                    "Unsupported cipher suite: {}",
+                    cipher
+                )));
+            }
+        }
+        provider.cipher_suites.retain(|suite| {
+            ciphers
+                .iter()
+                .any(|cipher| format!("{:?}", suite.suite()).eq_ignore_ascii_case(cipher))
+        });
+    }
+    Ok(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handler::connection::{Peer, handle_connection},
+        server::VirtualHosts,
+    };
+    use std::path::{Path, PathBuf};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_rustls::{
+        TlsConnector,
+        rustls::{ClientConfig, RootCertStore, pki_types::ServerName as SniName},
+    };
+
+    /// Self-signed certificate for `name`, written as PEM files to a temp directory
+    fn write_certificate(name: &str) -> (PathBuf, PathBuf, CertificateDer<'static>) {
+        let generated = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("rs-ngnix-tls-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let certificate_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        std::fs::write(&certificate_path, generated.cert.pem()).unwrap();
+        std::fs::write(&key_path, generated.signing_key.serialize_pem()).unwrap();
+        (certificate_path, key_path, generated.cert.der().clone())
+    }
+
+    /// Removes the temp directory `write_certificate` made
+    fn remove_certificate(certificate_path: &Path) {
+        std::fs::remove_dir_all(certificate_path.parent().unwrap()).unwrap();
+    }
+
+    fn server_config(
+        name: &str,
+        certificate_path: &PathBuf,
+        key_path: &PathBuf,
+        extra: &str,
+    ) -> ServerConfig {
+        serde_yaml::from_str(&format!(
+            "listen: 0\nserver_name: [\"{}\"]\nssl_certificate: {:?}\nssl_certificate_key: {:?}\nlocations:\n  - path: /\n    return: \"200 {}\"\n{}",
+            name, certificate_path, key_path, name, extra
+        ))
+        .unwrap()
+    }
+
+    async fn serve(configs: Vec<ServerConfig>) -> String {
+        let acceptor = tls_acceptor(&configs).unwrap().unwrap();
+        let hosts = Arc::new(VirtualHosts::new(&configs).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = listener.accept().await.unwrap();
+                let (acceptor, hosts) = (acceptor.clone(), hosts.clone());
+                tokio::spawn(async move {
+                    if let Ok(mut stream) = acceptor.accept(stream).await {
+                        let peer = Peer {
+                            addr,
+                            scheme: "https",
+                        };
+                        let _ = handle_connection(&mut stream, peer, &hosts).await;
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    fn connector(
+        roots: &[CertificateDer<'static>],
+        versions: &[&'static SupportedProtocolVersion],
+    ) -> TlsConnector {
+        let mut root_store = RootCertStore::empty();
+        for root in roots {
+            root_store.add(root.clone()).unwrap();
+        }
+        let mut config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_protocol_versions(versions)
+            .unwrap()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        TlsConnector::from(Arc::new(config))
+    }
+
+    #[tokio::test]
+    async fn test_sni_certificate_selection() {
+        let (one_certificate, one_key, one_der) = write_certificate("one.test");
+        let (two_certificate, two_key, two_der) = write_certificate("two.test");
+        let address = serve(vec![
+            server_config("one.test", &one_certificate, &one_key, ""),
+            server_config("two.test", &two_certificate, &two_key, ""),
+        ])
+        .await;
+
+        let connector = connector(&[one_der, two_der.clone()], &[&TLS12, &TLS13]);
+        let stream = TcpStream::connect(&address).await.unwrap();
+        let mut tls_stream = connector
+            .connect(SniName::try_from("two.test").unwrap(), stream)
+            .await
+            .unwrap();
+
+        let (_, connection) = tls_stream.get_ref();
+        assert_eq!(connection.alpn_protocol(), Some(&b"http/1.1"[..]));
+        assert_eq!(connection.peer_certificates().unwrap()[0], two_der);
+
+        tls_stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: two.test\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        tls_stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("two.test"));
+
+        remove_certificate(&one_certificate);
+        remove_certificate(&two_certificate);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_versions_and_ciphers() {
+        let (certificate, key, der) = write_certificate("three.test");
+        let address = serve(vec![server_config(
+            "three.test",
+            &certificate,
+            &key,
+            "ssl_protocols: [\"TLSv1.3\"]\nssl_ciphers: [\"TLS13_AES_256_GCM_SHA384\"]",
+        )])
+        .await;
+
+        let stream = TcpStream::connect(&address).await.unwrap();
+        let tls12_only = connector(std::slice::from_ref(&der), &[&TLS12]);
+        assert!(
+            tls12_only
+                .connect(SniName::try_from("three.test").unwrap(), stream)
+                .await
+                .is_err()
+        );
+
+        let stream = TcpStream::connect(&address).await.unwrap();
+        let tls_stream = connector(&[der], &[&TLS13])
+            .connect(SniName::try_from("three.test").unwrap(), stream)
+            .await
+            .unwrap();
+        let suite = tls_stream.get_ref().1.negotiated_cipher_suite().unwrap();
+        assert_eq!(format!("{:?}", suite.suite()), "TLS13_AES_256_GCM_SHA384");
+
+        let invalid = server_config("three.test", &certificate, &key, "ssl_ciphers: [\"RC4\"]");
+        assert!(tls_acceptor(&[invalid]).is_err());
+
+        remove_certificate(&certificate);
+    }
+}
@@ -70,7 +70,7 @@ mod tests {
     #[tokio::test]
     async fn test_health_probe() {
         // Test with a known good endpoint (assuming test servers are running)
-        let result = health_probe(&"127.0.0.1:3001".to_string(), "/health").await;
+        let result = health_probe("127.0.0.1:3001", "/health").await;
         println!("Health check result: {}", result);
     }
 }
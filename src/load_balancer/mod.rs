@@ -1,2 +1,4 @@
 pub mod health_check;
+pub mod pool;
 pub mod strategy;
+pub mod upstream;
@@ -0,0 +1,143 @@
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::net::TcpStream;
+
+use crate::http_parser::reader::HttpReader;
+
+pub const DEFAULT_UPSTREAM_KEEPALIVE_TIMEOUT: u64 = 60; // in s
+
+/// Keep-alive connection to a backend along with its read buffer
+pub struct UpstreamConnection {
+    pub stream: TcpStream,
+    pub reader: HttpReader,
+    pub reused: bool,
+}
+
+struct IdleConnection {
+    connection: UpstreamConnection,
+    since: Instant,
+}
+
+/// Idle upstream connections, kept per backend
+pub struct ConnectionPool {
+    idle: Mutex<Vec<VecDeque<IdleConnection>>>,
+    max_idle: usize,
+    idle_timeout: Duration,
+}
+
+impl UpstreamConnection {
+    pub async fn connect(
+        address: &str,
+        max_header_size: usize,
+    ) -> Result<UpstreamConnection, std::io::Error> {
+        Ok(UpstreamConnection {
+            stream: TcpStream::connect(address).await?,
+            reader: HttpReader::new(max_header_size),
+            reused: false,
+        })
+    }
+
+    /// An idle connection must have nothing to read, anything else means it was closed or is out of sync
+    fn is_idle(&self) -> bool {
+        let mut probe = [0u8; 1];
+        self.reader.is_empty()
+            && matches!(self.stream.try_read(&mut probe), Err(e) if e.kind() == ErrorKind::WouldBlock)
+    }
+}
+
+impl ConnectionPool {
+    pub fn new(backends: usize, max_idle: usize, idle_timeout: Duration) -> ConnectionPool {
+        ConnectionPool {
+            idle: Mutex::new((0..backends).map(|_| VecDeque::new()).collect()),
+            max_idle,
+            idle_timeout,
+        }
+    }
+
+    /// Most recently used live connection to `backend`, if any
+    pub fn take(&self, backend: usize) -> Option<UpstreamConnection> {
+        let mut idle = self.idle.lock().unwrap();
+        while let Some(idle_connection) = idle[backend].pop_back() {
+            if idle_connection.since.elapsed() < self.idle_timeout
+                && idle_connection.connection.is_idle()
+            {
+                let mut connection = idle_connection.connection;
+                connection.reused = true;
+                return Some(connection);
+            }
+        }
+        None
+    }
+
+    pub fn put(&self, backend: usize, connection: UpstreamConnection) {
+        if self.max_idle == 0 {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        let connections = &mut idle[backend];
+        connections.retain(|idle_connection| idle_connection.since.elapsed() < self.idle_timeout);
+        if connections.len() >= self.max_idle {
+            connections.pop_front();
+        }
+        connections.push_back(IdleConnection {
+            connection,
+            since: Instant::now(),
+        });
+    }
+
+    /// Drops every idle connection to `backend`
+    pub fn evict(&self, backend: usize) {
+        self.idle.lock().unwrap()[backend].clear();
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.max_idle > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_parser::reader::DEFAULT_MAX_HEADER_SIZE;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_pool_reuse_and_eviction() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let accept = tokio::spawn(async move {
+            let mut streams = Vec::new();
+            for _ in 0..3 {
+                streams.push(listener.accept().await.unwrap().0);
+            }
+            streams
+        });
+
+        let pool = ConnectionPool::new(1, 2, Duration::from_secs(60));
+        for _ in 0..3 {
+            let connection = UpstreamConnection::connect(&address, DEFAULT_MAX_HEADER_SIZE)
+                .await
+                .unwrap();
+            pool.put(0, connection);
+        }
+        let mut streams = accept.await.unwrap();
+
+        // only max_idle connections are kept, the newest one first
+        let newest = pool.take(0).unwrap();
+        assert!(newest.reused);
+
+        // connection closed by the backend while idle is skipped
+        streams.truncate(1);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(pool.take(0).is_none());
+
+        pool.put(0, newest);
+        pool.evict(0);
+        assert!(pool.take(0).is_none());
+    }
+}
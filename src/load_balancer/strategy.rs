@@ -25,7 +25,8 @@ pub struct WeightedRoundRobin {
 }
 impl Strategy for RoundRobin {
     fn get_next_server(&mut self, ctx: &Context) -> usize {
-        (self.current + 1) % ctx.size
+        self.current = (self.current + 1) % ctx.size;
+        self.current
     }
 }
 
@@ -0,0 +1,169 @@
+use std::{
+    ops::Add,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{sync::RwLock, time::sleep};
+
+use crate::{
+    config::{ProxyType, UpstreamConfig},
+    constants::strategies::{RANDOM, ROUND_ROBIN, WEIGHTED_ROUND_ROBIN},
+    load_balancer::{
+        health_check::check_health,
+        pool::{ConnectionPool, DEFAULT_UPSTREAM_KEEPALIVE_TIMEOUT, UpstreamConnection},
+        strategy::{Context, Random, RoundRobin, Strategy, WeightedRoundRobin},
+    },
+};
+
+/// Group of backend servers a proxy balances requests across
+pub struct Upstream {
+    pub addresses: Vec<String>,
+    health_result: Arc<RwLock<Vec<bool>>>,
+    strategy: Mutex<Box<dyn Strategy + Send + Sync>>,
+    context: Context,
+    pool: ConnectionPool,
+}
+
+impl Upstream {
+    pub fn new(config: &UpstreamConfig) -> Option<Upstream> {
+        let addresses = match config.proxy.clone()? {
+            ProxyType::Single(address) => vec![address],
+            ProxyType::Multiple(addresses) => addresses,
+        };
+        let proxy_size = addresses.len();
+        if proxy_size == 0 {
+            return None;
+        }
+
+        let health_result = Arc::new(RwLock::new(vec![true; proxy_size]));
+        if let Some(health_path) = &config.proxy_health {
+            check_health(
+                addresses.clone(),
+                health_path.clone(),
+                health_result.clone(),
+            );
+        }
+
+        Some(Upstream {
+            addresses,
+            health_result,
+            strategy: Mutex::new(get_load_balancer_strategy(config)),
+            context: Context {
+                size: proxy_size,
+                weights: get_server_weights(config, proxy_size),
+            },
+            pool: ConnectionPool::new(
+                proxy_size,
+                config.upstream_keepalive.unwrap_or(0),
+                Duration::from_secs(
+                    config
+                        .upstream_keepalive_timeout
+                        .unwrap_or(DEFAULT_UPSTREAM_KEEPALIVE_TIMEOUT),
+                ),
+            ),
+        })
+    }
+
+    /// Picks the next healthy backend, waiting a bit for one to recover if all are down
+    pub async fn get_healthy_server(&self) -> Option<usize> {
+        if self.addresses.len() == 1 {
+            return Some(0);
+        }
+
+        let mut iter_count = 0;
+        let mut fail_count: usize = 0;
+        let mut sleep_dur = Duration::from_secs(2);
+        loop {
+            if iter_count > self.context.size {
+                iter_count = 0;
+                fail_count += 1;
+
+                if fail_count > 3 {
+                    return None;
+                }
+                sleep(sleep_dur).await;
+                sleep_dur = sleep_dur.add(Duration::from_secs(1));
+            }
+
+            let current = self.strategy.lock().unwrap().get_next_server(&self.context);
+            if self.health_result.read().await[current] {
+                return Some(current);
+            }
+
+            iter_count += 1;
+        }
+    }
+
+    /// Idle pooled connection to `backend`, connections to unhealthy backends are dropped
+    pub async fn take_connection(&self, backend: usize) -> Option<UpstreamConnection> {
+        if !self.health_result.read().await[backend] {
+            self.pool.evict(backend);
+            return None;
+        }
+        self.pool.take(backend)
+    }
+
+    pub async fn connect(
+        &self,
+        backend: usize,
+        max_header_size: usize,
+    ) -> Result<UpstreamConnection, std::io::Error> {
+        let result = UpstreamConnection::connect(&self.addresses[backend], max_header_size).await;
+        if result.is_err() {
+            // whatever is left idle for this backend is most likely dead too
+            self.pool.evict(backend);
+        }
+        result
+    }
+
+    pub async fn release(&self, backend: usize, connection: UpstreamConnection) {
+        if self.health_result.read().await[backend] {
+            self.pool.put(backend, connection);
+        }
+    }
+
+    pub fn keeps_connections(&self) -> bool {
+        self.pool.enabled()
+    }
+}
+
+fn get_load_balancer_strategy(config: &UpstreamConfig) -> Box<dyn Strategy + Send + Sync> {
+    match &config.strategy {
+        Some(strategy) => {
+            // println!("Got strategy {}", strategy);
+            match strategy.as_str() {
+                ROUND_ROBIN => Box::new(RoundRobin { current: 0 }),
+                RANDOM => Box::new(Random {}),
+                WEIGHTED_ROUND_ROBIN => Box::new(WeightedRoundRobin {
+                    current: 0,
+                    current_count: 0,
+                }),
+                _ => {
+                    println!("Unknown strategy, proceeding with random");
+                    Box::new(Random {})
+                }
+            }
+        }
+        None => {
+            println!("No strategy, proceeding with random");
+            Box::new(Random {})
+        }
+    }
+}
+
+fn get_server_weights(config: &UpstreamConfig, proxy_size: usize) -> Vec<u8> {
+    if let Some(weights) = &config.weights {
+        let mut result = weights.clone();
+        if result.len() < proxy_size {
+            // Pad with default weight 1 if not enough weights are provided
+            result.extend(vec![1; proxy_size - result.len()]);
+        } else if result.len() > proxy_size {
+            // Truncate if too many weights are provided
+            result.truncate(proxy_size);
+        }
+        result
+    } else {
+        vec![1; proxy_size]
+    }
+}
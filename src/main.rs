@@ -6,9 +6,12 @@ mod compression;
 mod config;
 mod constants;
 mod handler;
+mod http_parser;
 mod listener;
 mod load_balancer;
 mod response_builder;
+mod router;
+mod server;
 
 #[tokio::main]
 async fn main() {
@@ -0,0 +1,250 @@
+use std::{collections::HashMap, io::Error, path::Path};
+
+use crate::{config::ServerConfig, http_parser::headers::Headers};
+
+pub const DEFAULT_TYPE: &str = "application/octet-stream";
+
+/// Extension to MIME type table, mostly following nginx's `mime.types`
+const MIME_TYPES: &[(&str, &str)] = &[
+    // text
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("shtml", "text/html"),
+    ("css", "text/css"),
+    ("txt", "text/plain"),
+    ("text", "text/plain"),
+    ("log", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("tsv", "text/tab-separated-values"),
+    ("xml", "text/xml"),
+    ("ics", "text/calendar"),
+    ("vtt", "text/vtt"),
+    ("mml", "text/mathml"),
+    ("jad", "text/vnd.sun.j2me.app-descriptor"),
+    ("htc", "text/x-component"),
+    // scripts and data
+    ("js", "application/javascript"),
+    ("mjs", "application/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("jsonld", "application/ld+json"),
+    ("webmanifest", "application/manifest+json"),
+    ("rss", "application/rss+xml"),
+    ("atom", "application/atom+xml"),
+    ("xhtml", "application/xhtml+xml"),
+    ("wasm", "application/wasm"),
+    // images
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("svgz", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/x-ms-bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("jng", "image/x-jng"),
+    // fonts
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("eot", "application/vnd.ms-fontobject"),
+    // audio
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/ogg"),
+    ("m4a", "audio/x-m4a"),
+    ("aac", "audio/aac"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("mid", "audio/midi"),
+    ("midi", "audio/midi"),
+    // video
+    ("mp4", "video/mp4"),
+    ("m4v", "video/x-m4v"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("mov", "video/quicktime"),
+    ("avi", "video/x-msvideo"),
+    ("mkv", "video/x-matroska"),
+    ("mpeg", "video/mpeg"),
+    ("mpg", "video/mpeg"),
+    ("flv", "video/x-flv"),
+    ("3gp", "video/3gpp"),
+    ("ts", "video/mp2t"),
+    ("m3u8", "application/vnd.apple.mpegurl"),
+    // documents and archives
+    ("pdf", "application/pdf"),
+    ("rtf", "application/rtf"),
+    ("doc", "application/msword"),
+    ("xls", "application/vnd.ms-excel"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("epub", "application/epub+zip"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("7z", "application/x-7z-compressed"),
+    ("rar", "application/x-rar-compressed"),
+    ("bz2", "application/x-bzip2"),
+    ("xz", "application/x-xz"),
+    ("jar", "application/java-archive"),
+    ("apk", "application/vnd.android.package-archive"),
+    ("deb", "application/octet-stream"),
+    ("dmg", "application/octet-stream"),
+    ("iso", "application/octet-stream"),
+    ("bin", "application/octet-stream"),
+    ("exe", "application/octet-stream"),
+];
+
+/// Types that get `charset=utf-8` appended when served
+const TEXT_TYPES: &[&str] = &[
+    "application/javascript",
+    "application/json",
+    "application/ld+json",
+    "application/manifest+json",
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/xhtml+xml",
+    "image/svg+xml",
+];
+
+/// Content types for one server block, `types` entries take precedence over the built-in table
+#[derive(Debug)]
+pub struct MimeTypes {
+    overrides: HashMap<String, String>,
+    default_type: String,
+    nosniff: bool,
+}
+
+impl MimeTypes {
+    pub fn new(config: &ServerConfig) -> Result<MimeTypes, Error> {
+        let mut overrides = HashMap::new();
+        for (extension, mime_type) in config.types.iter().flatten() {
+            validate_type(mime_type)?;
+            overrides.insert(
+                extension.trim_start_matches('.').to_ascii_lowercase(),
+                mime_type.clone(),
+            );
+        }
+        let default_type = config
+            .default_type
+            .clone()
+            .unwrap_or(DEFAULT_TYPE.to_string());
+        validate_type(&default_type)?;
+
+        Ok(MimeTypes {
+            overrides,
+            default_type,
+            nosniff: config.nosniff.unwrap_or(false),
+        })
+    }
+
+    /// `Content-Type` value for a file, with a charset for text types
+    pub fn content_type(&self, path: &Path) -> String {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let Some(extension) = extension else {
+            return self.default_type.clone();
+        };
+        if let Some(mime_type) = self.overrides.get(&extension) {
+            // configured values are used as written
+            return mime_type.clone();
+        }
+        match MIME_TYPES.iter().find(|(known, _)| *known == extension) {
+            Some((_, mime_type)) if is_text(mime_type) => format!("{}; charset=utf-8", mime_type),
+            Some((_, mime_type)) => mime_type.to_string(),
+            None => self.default_type.clone(),
+        }
+    }
+
+    /// Headers sent with every file response
+    pub fn extra_headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        if self.nosniff {
+            headers.append("X-Content-Type-Options", "nosniff");
+        }
+        headers
+    }
+}
+
+fn is_text(mime_type: &str) -> bool {
+    mime_type.starts_with("text/") || TEXT_TYPES.contains(&mime_type)
+}
+
+fn validate_type(mime_type: &str) -> Result<(), Error> {
+    let valid = mime_type.split_once('/').is_some_and(|(kind, subtype)| {
+        !kind.is_empty() && !subtype.is_empty() && !mime_type.contains(['\r', '\n'])
+    });
+    if !valid {
+        return Err(Error::other(format!("Invalid MIME type {}", mime_type)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mime_types(yaml: &str) -> MimeTypes {
+        let config: ServerConfig = serde_yaml::from_str(&format!("listen: 80\n{}", yaml)).unwrap();
+        MimeTypes::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_content_type() {
+        let mime_types = mime_types("");
+        let content_type = |path: &str| mime_types.content_type(Path::new(path));
+        assert_eq!(content_type("index.html"), "text/html; charset=utf-8");
+        assert_eq!(
+            content_type("app.JS"),
+            "application/javascript; charset=utf-8"
+        );
+        assert_eq!(content_type("module.wasm"), "application/wasm");
+        assert_eq!(content_type("logo.svg"), "image/svg+xml; charset=utf-8");
+        assert_eq!(content_type("clip.mp4"), "video/mp4");
+        assert_eq!(content_type("README"), DEFAULT_TYPE);
+        assert_eq!(content_type("data.unknown"), DEFAULT_TYPE);
+        assert_eq!(mime_types.extra_headers(), Headers::new());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mime_types = mime_types(
+            "types:\n  .md: text/plain\n  wasm: application/x-wasm\ndefault_type: text/plain\nnosniff: true",
+        );
+        let content_type = |path: &str| mime_types.content_type(Path::new(path));
+        assert_eq!(content_type("notes.md"), "text/plain");
+        assert_eq!(content_type("module.wasm"), "application/x-wasm");
+        assert_eq!(content_type("README"), "text/plain");
+        assert_eq!(
+            mime_types.extra_headers().get("x-content-type-options"),
+            Some("nosniff")
+        );
+
+        let config: ServerConfig = serde_yaml::from_str("listen: 80\ndefault_type: nope").unwrap();
+        assert!(MimeTypes::new(&config).is_err());
+    }
+}
@@ -1 +1,3 @@
-pub mod http;
+pub mod mime;
+pub mod response;
+pub mod status;
@@ -0,0 +1,128 @@
+use std::io::Error;
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    handler::connection::ClientStream, http_parser::headers::Headers,
+    response_builder::status::Status,
+};
+
+/// Response generated by the server, headers are written in the order they were added
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: Status,
+    pub headers: Headers,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: Status) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Appends every field of `headers`
+    pub fn headers(mut self, headers: &Headers) -> Response {
+        for (name, value) in headers.iter() {
+            self.headers.append(name, value);
+        }
+        self
+    }
+
+    /// Length of a body the caller streams after the head
+    pub fn content_length(self, length: u64) -> Response {
+        self.header("Content-Length", &length.to_string())
+    }
+
+    pub fn body(mut self, content_type: &str, body: impl Into<Vec<u8>>) -> Response {
+        self.body = body.into();
+        let length = self.body.len() as u64;
+        self.content_length(length)
+            .header("Content-Type", content_type)
+    }
+
+    /// Empty body, which still needs a length to keep the connection usable
+    pub fn empty(self) -> Response {
+        self.content_length(0)
+    }
+
+    pub fn keep_alive(self, keep_alive: bool) -> Response {
+        self.header("Connection", connection_header(keep_alive))
+    }
+
+    pub fn head(&self) -> String {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            self.status.code(),
+            self.status.reason()
+        );
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        head
+    }
+
+    /// Writes the head alone, for bodies streamed by the caller
+    pub async fn write_head<S: ClientStream>(&self, stream: &mut S) -> Result<(), Error> {
+        stream.write_all(self.head().as_bytes()).await
+    }
+
+    /// Writes the whole response, HEAD requests get the head only
+    pub async fn send<S: ClientStream>(
+        &self,
+        stream: &mut S,
+        head_only: bool,
+    ) -> Result<(), Error> {
+        self.write_head(stream).await?;
+        if !head_only {
+            stream.write_all(&self.body).await?;
+        }
+        stream.flush().await
+    }
+}
+
+pub fn connection_header(keep_alive: bool) -> &'static str {
+    if keep_alive { "keep-alive" } else { "close" }
+}
+
+/// Plain HTML body for an error without a configured `error_page`
+pub fn error_response(status: Status) -> Response {
+    let title = format!("{} {}", status.code(), status.reason());
+    Response::new(status).body(
+        "text/html; charset=utf-8",
+        format!(
+            "<html>\r\n<head><title>{}</title></head>\r\n<body>\r\n<center><h1>{}</h1></center>\r\n<hr><center>rs-ngnix</center>\r\n</body>\r\n</html>\r\n",
+            title, title
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_head() {
+        let response = Response::new(Status::NotFound)
+            .body("text/plain", "missing")
+            .header("X-Test", "1")
+            .keep_alive(false);
+        assert_eq!(
+            response.head(),
+            "HTTP/1.1 404 NOT FOUND\r\nContent-Length: 7\r\nContent-Type: text/plain\r\nX-Test: 1\r\nConnection: close\r\n\r\n"
+        );
+        assert_eq!(
+            Response::new(Status::Other(451)).empty().head(),
+            "HTTP/1.1 451 \r\nContent-Length: 0\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+/// Status codes this server sends itself, proxied responses keep the upstream status line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    Ok,
+    NoContent,
+    PartialContent,
+    MovedPermanently,
+    Found,
+    SeeOther,
+    NotModified,
+    TemporaryRedirect,
+    PermanentRedirect,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    RequestTimeout,
+    Gone,
+    PreconditionFailed,
+    RangeNotSatisfiable,
+    ImATeapot,
+    TooManyRequests,
+    HeaderFieldsTooLarge,
+    InternalServerError,
+    BadGateway,
+    ServiceUnavailable,
+    GatewayTimeout,
+    /// Any other code from configuration, such as `return 451`
+    Other(u16),
+}
+
+const KNOWN: [Status; 26] = [
+    Status::Ok,
+    Status::NoContent,
+    Status::PartialContent,
+    Status::MovedPermanently,
+    Status::Found,
+    Status::SeeOther,
+    Status::NotModified,
+    Status::TemporaryRedirect,
+    Status::PermanentRedirect,
+    Status::BadRequest,
+    Status::Unauthorized,
+    Status::Forbidden,
+    Status::NotFound,
+    Status::MethodNotAllowed,
+    Status::NotAcceptable,
+    Status::RequestTimeout,
+    Status::Gone,
+    Status::PreconditionFailed,
+    Status::RangeNotSatisfiable,
+    Status::ImATeapot,
+    Status::TooManyRequests,
+    Status::HeaderFieldsTooLarge,
+    Status::InternalServerError,
+    Status::BadGateway,
+    Status::ServiceUnavailable,
+    Status::GatewayTimeout,
+];
+
+impl Status {
+    pub fn from_code(code: u16) -> Status {
+        KNOWN
+            .into_iter()
+            .find(|status| status.code() == code)
+            .unwrap_or(Status::Other(code))
+    }
+
+    pub fn code(&self) -> u16 {
+        match self {
+            Status::Ok => 200,
+            Status::NoContent => 204,
+            Status::PartialContent => 206,
+            Status::MovedPermanently => 301,
+            Status::Found => 302,
+            Status::SeeOther => 303,
+            Status::NotModified => 304,
+            Status::TemporaryRedirect => 307,
+            Status::PermanentRedirect => 308,
+            Status::BadRequest => 400,
+            Status::Unauthorized => 401,
+            Status::Forbidden => 403,
+            Status::NotFound => 404,
+            Status::MethodNotAllowed => 405,
+            Status::NotAcceptable => 406,
+            Status::RequestTimeout => 408,
+            Status::Gone => 410,
+            Status::PreconditionFailed => 412,
+            Status::RangeNotSatisfiable => 416,
+            Status::ImATeapot => 418,
+            Status::TooManyRequests => 429,
+            Status::HeaderFieldsTooLarge => 431,
+            Status::InternalServerError => 500,
+            Status::BadGateway => 502,
+            Status::ServiceUnavailable => 503,
+            Status::GatewayTimeout => 504,
+            Status::Other(code) => *code,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::NoContent => "NO CONTENT",
+            Status::PartialContent => "PARTIAL CONTENT",
+            Status::MovedPermanently => "MOVED PERMANENTLY",
+            Status::Found => "FOUND",
+            Status::SeeOther => "SEE OTHER",
+            Status::NotModified => "NOT MODIFIED",
+            Status::TemporaryRedirect => "TEMPORARY REDIRECT",
+            Status::PermanentRedirect => "PERMANENT REDIRECT",
+            Status::BadRequest => "BAD REQUEST",
+            Status::Unauthorized => "UNAUTHORIZED",
+            Status::Forbidden => "FORBIDDEN",
+            Status::NotFound => "NOT FOUND",
+            Status::MethodNotAllowed => "METHOD NOT ALLOWED",
+            Status::NotAcceptable => "NOT ACCEPTABLE",
+            Status::RequestTimeout => "REQUEST TIMEOUT",
+            Status::Gone => "GONE",
+            Status::PreconditionFailed => "PRECONDITION FAILED",
+            Status::RangeNotSatisfiable => "RANGE NOT SATISFIABLE",
+            Status::ImATeapot => "I'M A TEAPOT",
+            Status::TooManyRequests => "TOO MANY REQUESTS",
+            Status::HeaderFieldsTooLarge => "REQUEST HEADER FIELDS TOO LARGE",
+            Status::InternalServerError => "INTERNAL SERVER ERROR",
+            Status::BadGateway => "BAD GATEWAY",
+            Status::ServiceUnavailable => "SERVICE UNAVAILABLE",
+            Status::GatewayTimeout => "GATEWAY TIMEOUT",
+            Status::Other(_) => "",
+        }
+    }
+
+    pub fn is_redirect(&self) -> bool {
+        matches!(self.code(), 301 | 302 | 303 | 307 | 308)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.code() >= 400
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        for status in KNOWN {
+            assert_eq!(Status::from_code(status.code()), status);
+            assert!(!status.reason().is_empty());
+        }
+        assert_eq!(Status::from_code(451), Status::Other(451));
+        assert_eq!(Status::from_code(451).code(), 451);
+        assert!(Status::MovedPermanently.is_redirect());
+        assert!(Status::NotFound.is_error() && !Status::NotModified.is_error());
+    }
+}
@@ -0,0 +1,73 @@
+use std::{io::Error, path::PathBuf};
+
+use crate::{
+    config::{LocationConfig, UpstreamConfig},
+    handler::proxy_handler::Proxy,
+    response_builder::status::Status,
+    router::{matcher::Matcher, try_files::TryFiles},
+};
+
+pub struct Location {
+    pub matcher: Matcher,
+    pub handler: LocationHandler,
+}
+
+pub enum LocationHandler {
+    Static {
+        root: PathBuf,
+        try_files: Option<TryFiles>,
+    },
+    Proxy(Proxy),
+    Return {
+        status: Status,
+        value: String,
+    },
+}
+
+impl Location {
+    pub fn new(config: &LocationConfig) -> Result<Location, Error> {
+        let handler = if let Some(root) = &config.root {
+            LocationHandler::Static {
+                root: PathBuf::from(root),
+                try_files: config
+                    .try_files
+                    .as_deref()
+                    .map(TryFiles::parse)
+                    .transpose()?,
+            }
+        } else if let Some(return_response) = &config.return_response {
+            let (status, value) = parse_return(return_response)?;
+            LocationHandler::Return {
+                status: Status::from_code(status),
+                value,
+            }
+        } else {
+            proxy_handler(&config.upstream)?
+                .ok_or_else(|| Error::other(format!("Location {} has no handler", config.path)))?
+        };
+
+        Ok(Location {
+            matcher: Matcher::parse(&config.path)?,
+            handler,
+        })
+    }
+}
+
+pub fn proxy_handler(config: &UpstreamConfig) -> Result<Option<LocationHandler>, Error> {
+    Ok(Proxy::new(config)?.map(LocationHandler::Proxy))
+}
+
+/// Splits `301 https://example.com` into the status code and its URL or body text
+pub fn parse_return(return_response: &str) -> Result<(u16, String), Error> {
+    let return_response = return_response.trim();
+    let (status, value) = return_response
+        .split_once(char::is_whitespace)
+        .unwrap_or((return_response, ""));
+    match status.parse::<u16>() {
+        Ok(status) if (100..600).contains(&status) => Ok((status, value.trim().to_string())),
+        _ => Err(Error::other(format!(
+            "Invalid return status: {}",
+            return_response
+        ))),
+    }
+}
@@ -0,0 +1,58 @@
+use std::io::Error;
+
+use regex::{Regex, RegexBuilder};
+
+/// URI pattern of a location, following nginx modifiers
+#[derive(Debug)]
+pub enum Matcher {
+    Exact(String),
+    Prefix {
+        path: String,
+        skip_regex: bool,
+    },
+    Regex(Regex),
+    /// `@name`, only reachable through internal redirects
+    Named(String),
+}
+
+impl Matcher {
+    pub fn parse(path: &str) -> Result<Matcher, Error> {
+        let path = path.trim();
+        if path.starts_with('@') {
+            return Ok(Matcher::Named(path.to_string()));
+        }
+        let (modifier, pattern) = match path.split_once(char::is_whitespace) {
+            Some((modifier, pattern)) => (modifier, pattern.trim()),
+            None => ("", path),
+        };
+
+        let matcher = match modifier {
+            "=" => Matcher::Exact(pattern.to_string()),
+            "^~" => Matcher::Prefix {
+                path: pattern.to_string(),
+                skip_regex: true,
+            },
+            "~" | "~*" => Matcher::Regex(
+                RegexBuilder::new(pattern)
+                    .case_insensitive(modifier == "~*")
+                    .build()
+                    .map_err(|e| {
+                        Error::other(format!("Invalid location regex {}: {}", pattern, e))
+                    })?,
+            ),
+            _ => Matcher::Prefix {
+                path: path.to_string(),
+                skip_regex: false,
+            },
+        };
+
+        match &matcher {
+            Matcher::Exact(pattern) | Matcher::Prefix { path: pattern, .. }
+                if !pattern.starts_with('/') =>
+            {
+                Err(Error::other(format!("Invalid location path: {}", path)))
+            }
+            _ => Ok(matcher),
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod location;
+pub mod matcher;
+pub mod routes;
+pub mod server_name;
+pub mod try_files;
@@ -0,0 +1,166 @@
+use std::{io::Error, path::PathBuf};
+
+use crate::{
+    config::ServerConfig,
+    router::{
+        location::{Location, LocationHandler, proxy_handler},
+        matcher::Matcher,
+        try_files::TryFiles,
+    },
+};
+
+/// Locations of a server, searched the way nginx does:
+/// exact match, then longest prefix unless it is `^~`, then regexes in order, then the longest prefix
+pub struct Router {
+    locations: Vec<Location>,
+}
+
+impl Router {
+    pub fn new(config: &ServerConfig) -> Result<Router, Error> {
+        let mut locations = config
+            .locations
+            .iter()
+            .flatten()
+            .map(Location::new)
+            .collect::<Result<Vec<Location>, Error>>()?;
+
+        // server level root or proxy handles everything no location claims
+        let has_root_location = locations.iter().any(
+            |location| matches!(&location.matcher, Matcher::Prefix { path, .. } if path == "/"),
+        );
+        let fallback = match &config.root {
+            Some(root) => Some(LocationHandler::Static {
+                root: PathBuf::from(root),
+                try_files: config
+                    .try_files
+                    .as_deref()
+                    .map(TryFiles::parse)
+                    .transpose()?,
+            }),
+            None => proxy_handler(&config.upstream)?,
+        };
+        if !has_root_location && let Some(handler) = fallback {
+            locations.push(Location {
+                matcher: Matcher::Prefix {
+                    path: "/".to_string(),
+                    skip_regex: false,
+                },
+                handler,
+            });
+        }
+
+        Ok(Router { locations })
+    }
+
+    pub fn find(&self, path: &str) -> Option<&Location> {
+        let mut longest: Option<(&Location, usize, bool)> = None;
+        for location in &self.locations {
+            match &location.matcher {
+                Matcher::Exact(exact) if exact == path => return Some(location),
+                Matcher::Prefix {
+                    path: prefix,
+                    skip_regex,
+                } if path.starts_with(prefix.as_str())
+                    && longest.is_none_or(|(_, length, _)| prefix.len() > length) =>
+                {
+                    longest = Some((location, prefix.len(), *skip_regex));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((location, _, true)) = longest {
+            return Some(location);
+        }
+        self.locations
+            .iter()
+            .find(|location| matches!(&location.matcher, Matcher::Regex(regex) if regex.is_match(path)))
+            .or(longest.map(|(location, _, _)| location))
+    }
+
+    /// `@name` location targeted by `try_files`
+    pub fn find_named(&self, name: &str) -> Option<&Location> {
+        self.locations
+            .iter()
+            .find(|location| matches!(&location.matcher, Matcher::Named(named) if named == name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LocationConfig;
+
+    fn location(path: &str, status: u16) -> LocationConfig {
+        LocationConfig {
+            path: path.to_string(),
+            root: None,
+            upstream: Default::default(),
+            return_response: Some(status.to_string()),
+            try_files: None,
+        }
+    }
+
+    fn matched_status(router: &Router, path: &str) -> Option<u16> {
+        match router.find(path).map(|location| &location.handler) {
+            Some(LocationHandler::Return { status, .. }) => Some(status.code()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_location_priority() {
+        let config: ServerConfig = serde_yaml::from_str("listen: 8080").unwrap();
+        let router = Router::new(&ServerConfig {
+            locations: Some(vec![
+                location("/", 200),
+                location("= /exact", 201),
+                location("/images/", 202),
+                location("^~ /static/", 203),
+                location("~* \\.(png|jpg)$", 204),
+                location("/images/icons/", 205),
+            ]),
+            ..config
+        })
+        .unwrap();
+
+        assert_eq!(matched_status(&router, "/exact"), Some(201));
+        assert_eq!(matched_status(&router, "/exact/more"), Some(200));
+        assert_eq!(matched_status(&router, "/images/a.txt"), Some(202));
+        assert_eq!(matched_status(&router, "/images/a.PNG"), Some(204));
+        assert_eq!(matched_status(&router, "/static/a.png"), Some(203));
+        assert_eq!(matched_status(&router, "/images/icons/x.gif"), Some(205));
+        assert_eq!(matched_status(&router, "/other"), Some(200));
+    }
+
+    #[test]
+    fn test_server_root_is_the_fallback() {
+        let config: ServerConfig = serde_yaml::from_str(
+            "listen: 8080\nroot: /srv\nlocations:\n  - path: /old/\n    return: 301 /new/",
+        )
+        .unwrap();
+        let router = Router::new(&config).unwrap();
+
+        assert_eq!(matched_status(&router, "/old/page"), Some(301));
+        assert!(matches!(
+            router.find("/page").map(|location| &location.handler),
+            Some(LocationHandler::Static { .. })
+        ));
+        assert!(Matcher::parse("~ (unclosed").is_err());
+        assert!(Matcher::parse("= relative").is_err());
+    }
+
+    #[test]
+    fn test_named_locations_are_only_found_by_name() {
+        let config: ServerConfig = serde_yaml::from_str("listen: 8080").unwrap();
+        let router = Router::new(&ServerConfig {
+            locations: Some(vec![location("@fallback", 200)]),
+            ..config
+        })
+        .unwrap();
+
+        assert_eq!(matched_status(&router, "/@fallback"), None);
+        assert!(router.find_named("@fallback").is_some());
+        assert!(router.find_named("@other").is_none());
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::Error;
+
+use regex::{Regex, RegexBuilder};
+
+/// Pattern of a `server_name` entry
+#[derive(Debug)]
+pub enum ServerName {
+    Exact(String),
+    LeadingWildcard(String),  // `*.example.com`, stored as `.example.com`
+    TrailingWildcard(String), // `www.example.*`, stored as `www.example.`
+    Regex(Regex),
+}
+
+impl ServerName {
+    pub fn parse(name: &str) -> Result<ServerName, Error> {
+        let name = name.trim();
+        if let Some(pattern) = name.strip_prefix('~') {
+            return RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map(ServerName::Regex)
+                .map_err(|e| Error::other(format!("Invalid server_name regex {}: {}", name, e)));
+        }
+
+        let name = name.to_ascii_lowercase();
+        if let Some(suffix) = name.strip_prefix('*')
+            && suffix.starts_with('.')
+            && !suffix.contains('*')
+        {
+            Ok(ServerName::LeadingWildcard(suffix.to_string()))
+        } else if let Some(prefix) = name.strip_suffix('*')
+            && prefix.ends_with('.')
+            && !prefix.contains('*')
+        {
+            Ok(ServerName::TrailingWildcard(prefix.to_string()))
+        } else if name.is_empty() || name.contains('*') {
+            Err(Error::other(format!("Invalid server_name: {}", name)))
+        } else {
+            Ok(ServerName::Exact(name))
+        }
+    }
+}
+
+/// Picks the server whose names match `host` best, following nginx precedence:
+/// exact name, longest leading wildcard, longest trailing wildcard, then the first matching regex
+pub fn find_server(names: &[Vec<ServerName>], host: &str) -> Option<usize> {
+    let host = normalize_host(host);
+    let mut leading: Option<(usize, usize)> = None;
+    let mut trailing: Option<(usize, usize)> = None;
+
+    for (index, server_names) in names.iter().enumerate() {
+        for name in server_names {
+            match name {
+                ServerName::Exact(exact) if *exact == host => return Some(index),
+                ServerName::LeadingWildcard(suffix)
+                    if host.ends_with(suffix.as_str())
+                        && host.len() > suffix.len()
+                        && leading.is_none_or(|(_, length)| suffix.len() > length) =>
+                {
+                    leading = Some((index, suffix.len()));
+                }
+                ServerName::TrailingWildcard(prefix)
+                    if host.starts_with(prefix.as_str())
+                        && host.len() > prefix.len()
+                        && trailing.is_none_or(|(_, length)| prefix.len() > length) =>
+                {
+                    trailing = Some((index, prefix.len()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    leading.or(trailing).map(|(index, _)| index).or_else(|| {
+        names.iter().position(|server_names| {
+            server_names
+                .iter()
+                .any(|name| matches!(name, ServerName::Regex(regex) if regex.is_match(&host)))
+        })
+    })
+}
+
+/// Lowercases a `Host` header value and drops its port and trailing dot
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let host = if host.starts_with('[') {
+        // IPv6 literal
+        host.split_inclusive(']').next().unwrap_or(host)
+    } else {
+        host.split(':').next().unwrap_or(host)
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<ServerName> {
+        names
+            .iter()
+            .map(|name| ServerName::parse(name).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_server_name_precedence() {
+        let servers = vec![
+            names(&["~^api\\d+\\.example\\.com$"]),
+            names(&["*.example.com"]),
+            names(&["*.cdn.example.com", "www.example.*"]),
+            names(&["www.example.com", "example.com"]),
+        ];
+
+        assert_eq!(find_server(&servers, "Example.COM:8080"), Some(3));
+        assert_eq!(find_server(&servers, "www.example.com."), Some(3));
+        assert_eq!(find_server(&servers, "img.cdn.example.com"), Some(2));
+        assert_eq!(find_server(&servers, "api1.example.com"), Some(1));
+        assert_eq!(find_server(&servers, "www.example.org"), Some(2));
+        assert_eq!(find_server(&servers, "api2.example.net"), None);
+        assert_eq!(find_server(&servers, "other.org"), None);
+        assert!(ServerName::parse("www.*.com").is_err());
+    }
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("[::1]:8080"), "[::1]");
+        assert_eq!(normalize_host("Example.com."), "example.com");
+    }
+}
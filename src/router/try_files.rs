@@ -0,0 +1,99 @@
+use std::io::Error;
+
+use crate::response_builder::status::Status;
+
+/// `try_files $uri $uri.html $uri/ =404`: candidates checked in order, the last entry is
+/// used when none of them exists
+#[derive(Debug, Clone, PartialEq)]
+pub struct TryFiles {
+    pub candidates: Vec<String>,
+    pub fallback: Fallback,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fallback {
+    Status(Status),
+    /// Internal redirect, the request is routed again with this URI
+    Uri(String),
+    /// Internal redirect to an `@name` location
+    Named(String),
+}
+
+impl TryFiles {
+    pub fn parse(entries: &[String]) -> Result<TryFiles, Error> {
+        let Some((last, candidates)) = entries.split_last() else {
+            return Err(Error::other("Invalid try_files: no entries"));
+        };
+        if candidates.is_empty() {
+            return Err(Error::other(
+                "Invalid try_files: at least one file and a fallback are needed",
+            ));
+        }
+        for candidate in candidates {
+            if !candidate.starts_with('/') && !candidate.starts_with("$uri") {
+                return Err(Error::other(format!(
+                    "Invalid try_files entry {}: expected a path or $uri",
+                    candidate
+                )));
+            }
+        }
+
+        let fallback = if let Some(status) = last.strip_prefix('=') {
+            match status.parse::<u16>() {
+                Ok(status) if (100..600).contains(&status) => {
+                    Fallback::Status(Status::from_code(status))
+                }
+                _ => {
+                    return Err(Error::other(format!("Invalid try_files status: {}", last)));
+                }
+            }
+        } else if last.starts_with('@') {
+            Fallback::Named(last.clone())
+        } else if last.starts_with('/') || last.starts_with("$uri") {
+            Fallback::Uri(last.clone())
+        } else {
+            return Err(Error::other(format!(
+                "Invalid try_files fallback {}: expected =code, @name or a URI",
+                last
+            )));
+        };
+
+        Ok(TryFiles {
+            candidates: candidates.to_vec(),
+            fallback,
+        })
+    }
+}
+
+/// Substitutes `$uri`, the only variable try_files entries support
+pub fn expand(entry: &str, uri: &str) -> String {
+    entry.replace("$uri", uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(entries: &[&str]) -> Result<TryFiles, Error> {
+        TryFiles::parse(&entries.iter().map(|e| e.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_try_files() {
+        let try_files = parse(&["$uri", "$uri.html", "$uri/", "=404"]).unwrap();
+        assert_eq!(try_files.candidates, ["$uri", "$uri.html", "$uri/"]);
+        assert_eq!(try_files.fallback, Fallback::Status(Status::NotFound));
+        assert_eq!(
+            parse(&["$uri", "/index.html"]).unwrap().fallback,
+            Fallback::Uri("/index.html".to_string())
+        );
+        assert_eq!(
+            parse(&["$uri", "@backend"]).unwrap().fallback,
+            Fallback::Named("@backend".to_string())
+        );
+        assert!(parse(&["=404"]).is_err());
+        assert!(parse(&["$uri", "=999"]).is_err());
+        assert!(parse(&["relative", "=404"]).is_err());
+        assert_eq!(expand("$uri.html", "/about"), "/about.html");
+    }
+}
@@ -0,0 +1,164 @@
+use std::{io::Error, path::PathBuf, sync::Arc, time::Duration};
+
+use notify::RecommendedWatcher;
+
+use crate::{
+    cache::{entry::FileCache, policy::PolicyKind, sweeper::sweep_expired, watcher::watch_roots},
+    compression::encoder::Compression,
+    config::ServerConfig,
+    handler::connection::ConnectionSettings,
+    handler::{
+        autoindex::AutoIndex, error_page::ErrorPages, forwarded::Forwarded,
+        precompressed::Precompressed, static_handler::DEFAULT_INDEX,
+    },
+    response_builder::mime::MimeTypes,
+    router::{
+        routes::Router,
+        server_name::{ServerName, find_server},
+    },
+};
+
+/// Seconds a cached file is served before its mtime is checked again, like nginx
+const DEFAULT_OPEN_FILE_CACHE_VALID: u64 = 60;
+
+/// Shards a cache is split into unless `cache_max_entry_size` says otherwise
+const DEFAULT_CACHE_SHARDS: usize = 8;
+
+/// Everything a connection needs to serve requests for one server block
+pub struct Server {
+    pub router: Router,
+    pub cache: Arc<FileCache>,
+    pub cache_valid: Duration,
+    _watcher: Option<RecommendedWatcher>, // evicts changed files while the server is alive
+    pub forwarded: Option<Forwarded>,
+    pub settings: ConnectionSettings,
+    pub mime_types: MimeTypes,
+    pub index: Vec<String>,
+    pub autoindex: Option<AutoIndex>,
+    pub error_pages: ErrorPages,
+    pub precompressed: Precompressed,
+    pub compression: Compression,
+}
+
+impl Server {
+    pub fn new(config: &ServerConfig) -> Result<Server, Error> {
+        let forwarded = match &config.forwarded_headers {
+            Some(forwarded_config) => Some(Forwarded::new(forwarded_config)?),
+            None => None,
+        };
+
+        let capacity = config.cache.unwrap_or(0) * 1024;
+        let max_entry_size = match config.cache_max_entry_size {
+            Some(max_entry_size) => max_entry_size * 1024,
+            None => capacity / DEFAULT_CACHE_SHARDS,
+        };
+        let policy = match &config.cache_policy {
+            Some(policy) => PolicyKind::parse(policy)?,
+            None => PolicyKind::default(),
+        };
+        let cache = Arc::new(
+            FileCache::new(capacity, max_entry_size, policy).with_expiry(
+                config.cache_ttl.map(Duration::from_secs),
+                config.cache_inactive.map(Duration::from_secs),
+            ),
+        );
+        let watcher = match cache.is_enabled() {
+            true => {
+                sweep_expired(&cache);
+                watch_roots(cache.clone(), &static_roots(config))
+            }
+            false => None,
+        };
+
+        Ok(Server {
+            router: Router::new(config)?,
+            cache,
+            cache_valid: Duration::from_secs(
+                config
+                    .open_file_cache_valid
+                    .unwrap_or(DEFAULT_OPEN_FILE_CACHE_VALID),
+            ),
+            _watcher: watcher,
+            forwarded,
+            settings: ConnectionSettings::new(config),
+            mime_types: MimeTypes::new(config)?,
+            index: config
+                .index
+                .clone()
+                .unwrap_or(DEFAULT_INDEX.map(String::from).to_vec()),
+            autoindex: AutoIndex::new(config)?,
+            error_pages: ErrorPages::new(config)?,
+            precompressed: Precompressed::new(config),
+            compression: Compression::new(config)?,
+        })
+    }
+}
+
+/// Directories static files are served from
+fn static_roots(config: &ServerConfig) -> Vec<PathBuf> {
+    let location_roots = config
+        .locations
+        .iter()
+        .flatten()
+        .filter_map(|location| location.root.as_ref());
+    config
+        .root
+        .iter()
+        .chain(location_roots)
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Server blocks sharing a port, picked by the request's `Host` header
+pub struct VirtualHosts {
+    names: Vec<Vec<ServerName>>,
+    servers: Vec<Server>,
+    default: usize,
+}
+
+impl VirtualHosts {
+    pub fn new(configs: &[ServerConfig]) -> Result<VirtualHosts, Error> {
+        let mut names = Vec::new();
+        let mut servers = Vec::new();
+        for config in configs {
+            names.push(
+                config
+                    .server_name
+                    .iter()
+                    .flatten()
+                    .map(|name| ServerName::parse(name))
+                    .collect::<Result<Vec<ServerName>, Error>>()?,
+            );
+            servers.push(Server::new(config)?);
+        }
+        if servers.is_empty() {
+            return Err(Error::other("No server to listen for"));
+        }
+
+        Ok(VirtualHosts {
+            names,
+            servers,
+            default: default_server_index(configs),
+        })
+    }
+
+    /// Server for a request, the default one when no name matches or `Host` is missing
+    pub fn find(&self, host: Option<&str>) -> &Server {
+        let index = host
+            .and_then(|host| find_server(&self.names, host))
+            .unwrap_or(self.default);
+        &self.servers[index]
+    }
+
+    pub fn default_server(&self) -> &Server {
+        &self.servers[self.default]
+    }
+}
+
+/// Server marked `default_server`, otherwise the first one
+pub fn default_server_index(configs: &[ServerConfig]) -> usize {
+    configs
+        .iter()
+        .position(|config| config.default_server.unwrap_or(false))
+        .unwrap_or(0)
+}